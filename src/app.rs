@@ -1,14 +1,27 @@
-use std::time::Duration;
+use std::{path::Path, time::Duration};
 
 use clap::{ArgGroup, Parser};
+use regex::Regex;
 use thiserror::Error;
 use tracing::info;
 
 use crate::{
+    asciicast::{Asciicast, AsciicastError, Frame},
     image_generator::{self, SaveError},
-    image_renderer::{ImageRenderer, ImageRendererError},
-    pty_executor::{PtyExecutor, PtyExecutorError, PtyOptions, dimension::Dimension},
-    terminal_builder::TerminalBuilderError,
+    image_renderer::{
+        BlinkStyle, CursorStyle, ImageRenderer, ImageRendererError, LinkStyle, PromptStyle,
+        RenderOptions,
+    },
+    input::{read_input, translate_line_feeds},
+    pty_executor::{
+        PtyExecutor, PtyExecutorError, PtyOptions,
+        dimension::Dimension,
+        input_script::{InputScript, InputScriptError, parse_duration},
+    },
+    terminal_builder::{
+        Capture, CommandOutput, LineSelection, ScreenBuffer, TaskProgress, TerminalBuilder,
+        TerminalBuilderError,
+    },
     theme::{Theme, ThemeError},
     window_decoration::{WindowDecorationType, create_window_decoration},
 };
@@ -20,6 +33,12 @@ pub enum ShellshotError {
     CommandExecution(#[from] PtyExecutorError),
     #[error("Failed to load theme: {0}")]
     ThemeError(#[from] ThemeError),
+    #[error("Failed to read input: {0}")]
+    Input(#[from] std::io::Error),
+    #[error("Failed to replay recording: {0}")]
+    Asciicast(#[from] AsciicastError),
+    #[error("Failed to load input script: {0}")]
+    InputScript(#[from] InputScriptError),
     #[error("Failed to build terminal from output: {0}")]
     TerminalBuild(#[from] TerminalBuilderError),
     #[error("Failed to render image: {0}")]
@@ -42,9 +61,24 @@ pub enum ShellshotError {
 )]
 pub struct Args {
     /// Command to execute
-    #[arg(trailing_var_arg = true, required = true)]
+    #[arg(trailing_var_arg = true, required_unless_present = "input")]
     pub command: Vec<String>,
 
+    /// Render output recorded beforehand, from a file or `-` for stdin,
+    /// instead of running a command
+    #[arg(long, short = 'i', value_name = "FILE", conflicts_with = "command")]
+    pub input: Option<String>,
+
+    /// Frame of an asciicast `--input` to render: 'end', a time like '12.5s'
+    /// or 'marker:<label>'
+    #[arg(
+        long,
+        default_value = "end",
+        requires = "input",
+        conflicts_with = "command"
+    )]
+    pub frame: Frame,
+
     /// Do not print anything to stdout
     #[arg(long, short = 'q')]
     pub quiet: bool,
@@ -95,9 +129,97 @@ pub struct Args {
     pub height: Dimension,
 
     /// Timeout in seconds for command execution
-    #[arg(long, short = 't')]
+    #[arg(long, short = 't', conflicts_with = "input")]
     pub timeout: Option<u64>,
 
+    /// End the capture once this regex matches the screen, and kill the
+    /// command
+    #[arg(
+        long,
+        value_name = "REGEX",
+        value_parser = Regex::new,
+        conflicts_with = "input"
+    )]
+    pub until: Option<Regex>,
+
+    /// End the capture once the command has printed nothing for this long,
+    /// e.g. `500ms` or `2s`, and kill the command
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = duration_arg,
+        conflicts_with = "input"
+    )]
+    pub idle: Option<Duration>,
+
+    /// End the capture this long after the start, e.g. `500ms` or `2s`, and
+    /// kill the command
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = duration_arg,
+        conflicts_with = "input"
+    )]
+    pub capture_at: Option<Duration>,
+
+    /// Columns between the default tab stops
+    #[arg(long, default_value = "8", value_parser = clap::value_parser!(u16).range(1..))]
+    pub tab_width: u16,
+
+    /// Screen buffer to capture: the primary screen, or the last frame a
+    /// full-screen program drew on the alternate screen
+    #[arg(long, default_value = "primary")]
+    pub screen: ScreenBuffer,
+
+    /// Lines to capture: 'screen' for the final screen, 'all' for the whole
+    /// history including the scrollback, a count N for the last N lines, or
+    /// a 1-based range like '10-20'
+    #[arg(long, default_value = "screen")]
+    pub lines: LineSelection,
+
+    /// Output to keep in a session whose shell marks its prompts with OSC 133:
+    /// 'all' for every line, 'last' for the output of the last command, or a
+    /// 1-based command number N
+    #[arg(long, default_value = "all")]
+    pub command_output: CommandOutput,
+
+    /// Text of the window title bar; defaults to the title set by the
+    /// command, or the command itself
+    #[arg(long)]
+    pub title: Option<String>,
+
+    /// How to draw OSC 8 hyperlinks; PNG images cannot hold the link targets
+    #[arg(long, default_value = "none")]
+    pub link_style: LinkStyle,
+
+    /// How to draw the prompts marked by OSC 133 shell integration
+    #[arg(long, default_value = "plain")]
+    pub prompt_style: PromptStyle,
+
+    /// How to draw blinking text, which a screenshot cannot animate
+    #[arg(long, default_value = "visible")]
+    pub blink_style: BlinkStyle,
+
+    /// Draw the cursor where the command left it; `auto` uses the shape the
+    /// command chose
+    #[arg(long, default_value = "none")]
+    pub cursor: CursorStyle,
+
+    /// Draw the progress last reported with OSC 9;4 as a strip along the
+    /// title bar
+    #[arg(long)]
+    pub progress: bool,
+
+    /// Script of keystrokes to type into the command: `type <text>`,
+    /// `key <names>`, `sleep <duration>` and `wait <regex>`, one per line
+    #[arg(long, value_name = "FILE", conflicts_with = "input")]
+    pub script: Option<String>,
+
+    /// Also record the session as an asciicast v2 file next to the image,
+    /// with the extension `.cast`
+    #[arg(long, conflicts_with_all = ["clipboard", "input"])]
+    pub record: bool,
+
     /// Force execution inside a shell (sh on Linux/macOS, bash on Windows)
     #[arg(long)]
     pub shell: bool,
@@ -113,14 +235,6 @@ pub struct Args {
 /// - Image rendering fails
 /// - Saving the image fails
 pub fn run_shellshot(args: Args) -> Result<(), ShellshotError> {
-    let pty_options = PtyOptions {
-        cols: args.width,
-        rows: args.height,
-        timeout: args.timeout.map(Duration::from_secs),
-        shell: args.shell,
-        quiet: args.quiet,
-    };
-
     let decoration = (!args.no_decoration).then_some(args.decoration);
     let window_decoration = create_window_decoration(decoration.as_ref());
 
@@ -130,9 +244,64 @@ pub fn run_shellshot(args: Args) -> Result<(), ShellshotError> {
         Theme::default()
     };
 
-    let screen = PtyExecutor::run_command(&pty_options, &args.command)?;
+    let pty_options = PtyOptions {
+        cols: args.width,
+        rows: args.height,
+        timeout: args.timeout.map(Duration::from_secs),
+        until: args.until,
+        idle: args.idle,
+        capture_at: args.capture_at,
+        screen: args.screen,
+        lines: args.lines,
+        command_output: args.command_output,
+        theme,
+        tab_width: usize::from(args.tab_width),
+        shell: args.shell,
+        quiet: args.quiet,
+        show_cursor: args.cursor != CursorStyle::None,
+        script: args.script.as_deref().map(InputScript::load).transpose()?,
+        record: args
+            .output
+            .as_deref()
+            .filter(|_| args.record)
+            .map(|output| Path::new(output).with_extension("cast")),
+    };
+
+    let (capture, recording_title) = match &args.input {
+        Some(input) => capture_input(input, &args.frame, pty_options)?,
+        None => {
+            let capture = PtyExecutor::run_command(&pty_options, &args.command)?;
+            if let Some(record) = &pty_options.record {
+                info!("✅ Recording saved to {}", record.display());
+            }
+            (capture, None)
+        }
+    };
+    // The title of a recording comes after the titles set while it was recorded
+    let title = args
+        .title
+        .clone()
+        .or_else(|| recording_title.filter(|_| capture.surface.title().is_empty()));
 
-    let image_data = ImageRenderer::render_image(&args.command, &screen, window_decoration, theme)?;
+    let render_options = RenderOptions {
+        link_style: args.link_style,
+        prompt_style: args.prompt_style,
+        blink_style: args.blink_style,
+        cursor_style: args.cursor,
+        progress: if args.progress {
+            capture.progress
+        } else {
+            TaskProgress::None
+        },
+        title,
+    };
+    let image_data = ImageRenderer::render_image(
+        &args.command,
+        &capture.surface,
+        window_decoration,
+        capture.theme,
+        render_options,
+    )?;
 
     if args.clipboard {
         image_generator::save_to_clipboard(&image_data)?;
@@ -147,41 +316,179 @@ pub fn run_shellshot(args: Args) -> Result<(), ShellshotError> {
     Ok(())
 }
 
+/// Builds the capture from recorded output: an asciicast recording replayed
+/// up to `frame` at the size of its header, or raw terminal output. Returns
+/// the title of the recording along with it.
+fn capture_input(
+    path: &str,
+    frame: &Frame,
+    mut pty_options: PtyOptions,
+) -> Result<(Capture, Option<String>), ShellshotError> {
+    let input = read_input(path)?;
+    if !Asciicast::is_asciicast(&input) {
+        if *frame != Frame::End {
+            return Err(AsciicastError::FrameWithoutRecording.into());
+        }
+        let output = translate_line_feeds(&input);
+        return Ok((TerminalBuilder::from_output(output, &pty_options)?, None));
+    }
+
+    let recording = Asciicast::parse(&input)?;
+    if matches!(pty_options.cols, Dimension::Auto) {
+        pty_options.cols = Dimension::Value(recording.header.width);
+    }
+    if matches!(pty_options.rows, Dimension::Auto) {
+        pty_options.rows = Dimension::Value(recording.header.height);
+    }
+    let capture = TerminalBuilder::from_output(recording.output(frame)?, &pty_options)?;
+
+    Ok((capture, recording.header.title))
+}
+
+fn duration_arg(value: &str) -> Result<Duration, String> {
+    parse_duration(value)
+        .ok_or_else(|| format!("invalid duration `{value}`, expected e.g. `500ms` or `2s`"))
+}
+
 #[cfg(test)]
 mod tests {
     use tempfile::tempdir;
 
     use super::*;
 
-    #[test]
-    fn test_execute_command_with_file() {
+    /// Arguments as parsed without any option, saving a quiet capture to `output`
+    fn args(output: &Path) -> Args {
+        Args {
+            command: Vec::new(),
+            input: None,
+            frame: Frame::End,
+            quiet: true,
+            no_decoration: false,
+            decoration: WindowDecorationType::Classic,
+            theme: None,
+            output: Some(output.to_str().unwrap().to_string()),
+            clipboard: false,
+            width: Dimension::Auto,
+            height: Dimension::Auto,
+            timeout: None,
+            until: None,
+            idle: None,
+            capture_at: None,
+            tab_width: 8,
+            screen: ScreenBuffer::Primary,
+            lines: LineSelection::Screen,
+            command_output: CommandOutput::All,
+            link_style: LinkStyle::None,
+            prompt_style: PromptStyle::Plain,
+            blink_style: BlinkStyle::Visible,
+            cursor: CursorStyle::None,
+            progress: false,
+            script: None,
+            record: false,
+            title: None,
+            shell: false,
+        }
+    }
+
+    fn echo_command() -> Vec<String> {
         let base_command = vec!["echo".to_string(), "hello".to_string()];
 
-        let command: Vec<String> = if cfg!(windows) && base_command[0] == "echo" {
+        if cfg!(windows) && base_command[0] == "echo" {
             vec!["cmd".into(), "/C".into(), base_command[1..].join(" ")]
         } else {
             base_command
+        }
+    }
+
+    #[test]
+    fn test_execute_command_with_file() {
+        let tmp = tempdir().unwrap();
+        let nested = tmp.path().join("nested/folder/test.png");
+
+        let args = Args {
+            command: echo_command(),
+            ..args(&nested)
         };
 
+        let result = run_shellshot(args);
+        assert!(result.is_ok());
+        assert!(nested.exists());
+    }
+
+    #[test]
+    fn test_record_command() {
         let tmp = tempdir().unwrap();
         let nested = tmp.path().join("nested/folder/test.png");
 
         let args = Args {
-            command,
-            quiet: true,
-            no_decoration: false,
-            decoration: WindowDecorationType::Classic,
-            theme: None,
-            output: Some(nested.to_str().unwrap().to_string()),
-            clipboard: false,
-            width: Dimension::Auto,
-            height: Dimension::Auto,
-            timeout: None,
-            shell: false,
+            command: echo_command(),
+            record: true,
+            ..args(&nested)
+        };
+
+        let result = run_shellshot(args);
+        assert!(result.is_ok());
+        assert!(nested.exists());
+        assert!(nested.with_extension("cast").exists());
+    }
+
+    #[test]
+    fn test_render_input_file() {
+        let tmp = tempdir().unwrap();
+        let nested = tmp.path().join("nested/folder/test.png");
+        let log = tmp.path().join("build.log");
+        std::fs::write(&log, "\x1b[32mok\x1b[0m build\nline two\n").unwrap();
+
+        let args = Args {
+            input: Some(log.to_str().unwrap().to_string()),
+            ..args(&nested)
         };
 
         let result = run_shellshot(args);
         assert!(result.is_ok());
         assert!(nested.exists());
     }
+
+    #[test]
+    fn test_frame_requires_input() {
+        let parse =
+            |args: &[&str]| Args::try_parse_from([&["shellshot", "-o", "out.png"], args].concat());
+        assert!(parse(&["--frame", "2s", "echo", "hi"]).is_err());
+        assert!(parse(&["--frame", "2s"]).is_err());
+        assert!(parse(&["-i", "demo.cast", "--frame", "2s"]).is_ok());
+        assert!(parse(&["echo", "hi"]).is_ok());
+    }
+
+    #[test]
+    fn test_command_options_conflict_with_input() {
+        let parse =
+            |args: &[&str]| Args::try_parse_from([&["shellshot", "-o", "out.png"], args].concat());
+        assert!(parse(&["-i", "build.log", "--timeout", "5"]).is_err());
+        assert!(parse(&["-i", "build.log", "--until", "done"]).is_err());
+        assert!(parse(&["-i", "build.log", "--idle", "2s"]).is_err());
+        assert!(parse(&["-i", "build.log", "--capture-at", "2s"]).is_err());
+        assert!(parse(&["-i", "build.log", "--script", "keys.txt"]).is_err());
+        assert!(parse(&["--timeout", "5", "--idle", "2s", "echo", "hi"]).is_ok());
+    }
+
+    #[test]
+    fn test_frame_of_plain_input_is_rejected() {
+        let tmp = tempdir().unwrap();
+        let log = tmp.path().join("build.log");
+        std::fs::write(&log, "plain log\n").unwrap();
+
+        let args = Args {
+            input: Some(log.to_str().unwrap().to_string()),
+            frame: Frame::Time(Duration::from_secs(2)),
+            ..args(&tmp.path().join("out.png"))
+        };
+
+        let result = run_shellshot(args);
+        assert!(matches!(
+            result,
+            Err(ShellshotError::Asciicast(
+                AsciicastError::FrameWithoutRecording
+            ))
+        ));
+    }
 }
@@ -0,0 +1,354 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::pty_executor::input_script::parse_duration;
+
+#[derive(Debug, Error)]
+pub enum AsciicastError {
+    #[error("Invalid asciicast: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Unsupported asciicast version {0}, only version 2 is supported")]
+    UnsupportedVersion(u32),
+
+    #[error("Marker `{0}` not found in the recording")]
+    MarkerNotFound(String),
+
+    #[error("A frame can only be chosen in an asciicast recording")]
+    FrameWithoutRecording,
+}
+
+/// Frame of a recording to render
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Frame {
+    /// The screen at the end of the recording
+    #[default]
+    End,
+    /// The screen at this time since the start of the recording
+    Time(Duration),
+    /// The screen at the first marker with this label
+    Marker(String),
+}
+
+impl FromStr for Frame {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("end") {
+            return Ok(Self::End);
+        }
+        if let Some(label) = s.strip_prefix("marker:") {
+            return Ok(Self::Marker(label.to_string()));
+        }
+        parse_duration(s).map(Self::Time).ok_or_else(|| {
+            format!("Invalid frame: {s}. Must be 'end', a time like '12.5s' or 'marker:<label>'")
+        })
+    }
+}
+
+/// First line of an asciicast v2 recording
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Header {
+    pub version: u32,
+    pub width: u16,
+    pub height: u16,
+    /// Start of the recording, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Event of a recording: time in seconds, event code and data
+#[derive(Debug, Deserialize, Serialize)]
+struct Event(f64, String, String);
+
+/// A recording in the asciicast v2 format, as written by asciinema
+#[derive(Debug)]
+pub struct Asciicast {
+    pub header: Header,
+    events: Vec<Event>,
+}
+
+impl Asciicast {
+    /// Whether `input` starts with an asciicast v2 header.
+    pub fn is_asciicast(input: &[u8]) -> bool {
+        let first_line = input
+            .split(|&byte| byte == b'\n')
+            .next()
+            .unwrap_or_default();
+        serde_json::from_slice::<Header>(first_line).is_ok()
+    }
+
+    /// Parses a recording: a header line followed by one event per line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a line is not valid JSON or the version is not 2.
+    pub fn parse(input: &[u8]) -> Result<Self, AsciicastError> {
+        let mut lines = input
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.trim_ascii().is_empty());
+
+        let header: Header = serde_json::from_slice(lines.next().unwrap_or_default())?;
+        if header.version != 2 {
+            return Err(AsciicastError::UnsupportedVersion(header.version));
+        }
+
+        let events = lines
+            .map(serde_json::from_slice)
+            .collect::<Result<Vec<Event>, _>>()?;
+
+        Ok(Self { header, events })
+    }
+
+    /// Output written up to `frame`. Resizes during the recording are not
+    /// replayed: the whole output is drawn at the size of the header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the marker of `frame` is not in the recording.
+    pub fn output(&self, frame: &Frame) -> Result<Vec<u8>, AsciicastError> {
+        let end = match frame {
+            Frame::End => self.events.len(),
+            Frame::Time(time) => self
+                .events
+                .iter()
+                .position(|Event(event_time, _, _)| *event_time > time.as_secs_f64())
+                .unwrap_or(self.events.len()),
+            Frame::Marker(label) => self
+                .events
+                .iter()
+                .position(|Event(_, code, data)| code == "m" && data == label)
+                .ok_or_else(|| AsciicastError::MarkerNotFound(label.clone()))?,
+        };
+
+        let events = &self.events[..end];
+        if events.iter().any(|Event(_, code, _)| code == "r") {
+            warn!("The recording was resized, it is replayed at its initial size");
+        }
+
+        Ok(events
+            .iter()
+            .filter(|Event(_, code, _)| code == "o")
+            .flat_map(|Event(_, _, data)| data.bytes())
+            .collect())
+    }
+}
+
+/// Writes a session in the asciicast v2 format as its output arrives
+pub struct AsciicastWriter {
+    writer: Box<dyn Write + Send>,
+    start: Instant,
+    /// Bytes of a UTF-8 character split across two reads
+    pending: Vec<u8>,
+}
+
+impl std::fmt::Debug for AsciicastWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsciicastWriter")
+            .field("start", &self.start)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsciicastWriter {
+    /// Creates the recording at `path`, and its missing parent directories,
+    /// for a terminal of `width` x `height`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created or written.
+    pub fn create(path: &Path, width: u16, height: u16, title: &str) -> io::Result<Self> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|since_epoch| since_epoch.as_secs());
+        let header = Header {
+            version: 2,
+            width,
+            height,
+            timestamp,
+            title: Some(title.to_string()).filter(|title| !title.is_empty()),
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Self::new(Box::new(BufWriter::new(File::create(path)?)), &header)
+    }
+
+    fn new(mut writer: Box<dyn Write + Send>, header: &Header) -> io::Result<Self> {
+        serde_json::to_writer(&mut writer, header)?;
+        writer.write_all(b"\n")?;
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Records `data` as an output event at the current time. Invalid UTF-8
+    /// is replaced, and a character cut at the end waits for the next call.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording cannot be written.
+    pub fn output(&mut self, data: &[u8]) -> io::Result<()> {
+        self.pending.extend_from_slice(data);
+        let complete = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+        let rest = self.pending.split_off(complete);
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending = rest;
+        self.write_event(text)
+    }
+
+    /// Writes what is left and flushes the recording.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recording cannot be written.
+    pub fn finish(mut self) -> io::Result<()> {
+        let text = String::from_utf8_lossy(&std::mem::take(&mut self.pending)).into_owned();
+        self.write_event(text)?;
+        self.writer.flush()
+    }
+
+    fn write_event(&mut self, text: String) -> io::Result<()> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        // Microseconds, as asciinema writes them
+        let time = self.start.elapsed().as_micros() as f64 / 1_000_000.0;
+        serde_json::to_writer(&mut self.writer, &Event(time, "o".to_string(), text))?;
+        self.writer.write_all(b"\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    const CAST: &str = r#"{"version": 2, "width": 40, "height": 10, "timestamp": 1700000000, "title": "demo"}
+[0.1, "o", "$ "]
+[0.5, "i", "ls\r"]
+[0.6, "o", "ls\r\n"]
+[1.0, "m", "listing"]
+[1.2, "o", "file.txt\r\n"]
+[2.0, "o", "$ "]
+"#;
+
+    fn output(frame: &Frame) -> String {
+        let cast = Asciicast::parse(CAST.as_bytes()).expect("recording should parse");
+        String::from_utf8(cast.output(frame).expect("frame should exist")).expect("utf-8")
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert!(Asciicast::is_asciicast(CAST.as_bytes()));
+        assert!(!Asciicast::is_asciicast(b"{\"level\": \"info\"}\n"));
+        assert!(!Asciicast::is_asciicast(b"plain log\n"));
+
+        let cast = Asciicast::parse(CAST.as_bytes()).expect("recording should parse");
+        assert_eq!((cast.header.width, cast.header.height), (40, 10));
+        assert_eq!(cast.header.title.as_deref(), Some("demo"));
+    }
+
+    #[test]
+    fn test_output_at_frames() {
+        assert_eq!(output(&Frame::End), "$ ls\r\nfile.txt\r\n$ ");
+        assert_eq!(
+            output(&Frame::Time(Duration::from_millis(1200))),
+            "$ ls\r\nfile.txt\r\n"
+        );
+        assert_eq!(output(&Frame::Time(Duration::from_secs(1))), "$ ls\r\n");
+        assert_eq!(output(&Frame::Marker("listing".to_string())), "$ ls\r\n");
+    }
+
+    #[test]
+    fn test_errors() {
+        let cast = Asciicast::parse(CAST.as_bytes()).expect("recording should parse");
+        assert!(matches!(
+            cast.output(&Frame::Marker("missing".to_string())),
+            Err(AsciicastError::MarkerNotFound(_))
+        ));
+        assert!(matches!(
+            Asciicast::parse(br#"{"version": 1, "width": 1, "height": 1}"#),
+            Err(AsciicastError::UnsupportedVersion(1))
+        ));
+        assert!(matches!(
+            Asciicast::parse(b"{\"version\": 2, \"width\": 1, \"height\": 1}\n[0.1, \"o\"\n"),
+            Err(AsciicastError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_frame_from_str() {
+        assert_eq!(Frame::from_str("end"), Ok(Frame::End));
+        assert_eq!(
+            Frame::from_str("2.5s"),
+            Ok(Frame::Time(Duration::from_millis(2500)))
+        );
+        assert_eq!(
+            Frame::from_str("marker:intro"),
+            Ok(Frame::Marker("intro".to_string()))
+        );
+        assert!(Frame::from_str("later").is_err());
+    }
+
+    /// Collects what is written, to read it back after the writer is dropped
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_written_recording_replays() {
+        let buffer = SharedBuffer::default();
+        let header = Header {
+            version: 2,
+            width: 20,
+            height: 4,
+            timestamp: None,
+            title: Some("echo é".to_string()),
+        };
+        let mut writer = AsciicastWriter::new(Box::new(buffer.clone()), &header).unwrap();
+        // "é" is cut in two between the reads
+        writer.output(b"\x1b[1mhello \xc3").unwrap();
+        writer.output(b"\xa9\r\n").unwrap();
+        writer.output(b"bad \xff").unwrap();
+        writer.finish().unwrap();
+
+        let recording = buffer.0.lock().unwrap().clone();
+        let cast = Asciicast::parse(&recording).expect("recording should parse");
+        assert_eq!((cast.header.width, cast.header.height), (20, 4));
+        assert_eq!(cast.header.title.as_deref(), Some("echo é"));
+        assert_eq!(cast.events.len(), 3);
+        assert_eq!(
+            String::from_utf8(cast.output(&Frame::End).unwrap()).unwrap(),
+            "\x1b[1mhello é\r\nbad \u{fffd}"
+        );
+    }
+}
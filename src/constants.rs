@@ -5,3 +5,9 @@ pub const IMAGE_QUALITY_MULTIPLIER: u32 = 3;
 // Constants for screen builder
 pub const SCREEN_MAX_HEIGHT: u16 = 1000;
 pub const SCREEN_MAX_WIDTH: u16 = 250;
+pub const SCROLLBACK_MAX_LINES: usize = 100_000;
+pub const TAB_WIDTH: usize = 8;
+
+// Size of a cell in image pixels, the size of a DejaVu Sans Mono cell at FONT_SIZE
+pub const CELL_PIXEL_WIDTH: u32 = 11;
+pub const CELL_PIXEL_HEIGHT: u32 = 20;
@@ -1,6 +1,9 @@
 use ab_glyph::PxScale;
-use image::RgbaImage;
-use termwiz::surface::Surface;
+use clap::ValueEnum;
+use image::{Rgba, RgbaImage};
+use termwiz::cell::{Blink, Cell, Intensity, SemanticType, Underline};
+use termwiz::color::{ColorAttribute, SrgbaTuple};
+use termwiz::surface::{CursorShape, CursorVisibility, Surface};
 use thiserror::Error;
 use tracing::info;
 use unicode_width::UnicodeWidthChar;
@@ -8,6 +11,7 @@ use unicode_width::UnicodeWidthChar;
 use crate::constants::{FONT_SIZE, IMAGE_QUALITY_MULTIPLIER};
 use crate::image_renderer::canvas::Canvas;
 use crate::image_renderer::render_size::{calculate_char_size, calculate_image_size};
+use crate::terminal_builder::TaskProgress;
 use crate::theme::Theme;
 use crate::window_decoration::{WindowDecoration, WindowMetrics};
 
@@ -30,14 +34,84 @@ pub enum ImageRendererError {
     ImageCreationFailed,
 }
 
+/// How cells carrying an OSC 8 hyperlink are drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LinkStyle {
+    /// Draw links like the surrounding text
+    #[default]
+    None,
+    /// Underline links that are not already underlined
+    Underline,
+}
+
+/// How prompts marked by OSC 133 shell integration are drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PromptStyle {
+    /// Draw prompts like the surrounding text
+    #[default]
+    Plain,
+    /// Draw prompts in bold
+    Bold,
+    /// Draw prompts faint, so the commands and their output stand out
+    Dim,
+}
+
+/// How blinking text is drawn, since a screenshot cannot blink
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum BlinkStyle {
+    /// Draw blinking text in its visible phase
+    #[default]
+    Visible,
+    /// Draw blinking text in its hidden phase, keeping its background
+    Hidden,
+    /// Draw blinking text faint
+    Dim,
+}
+
+/// Whether and how the terminal cursor is drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum CursorStyle {
+    /// Do not draw the cursor
+    #[default]
+    None,
+    /// Draw the cursor with the shape the program chose (DECSCUSR), a block by default
+    Auto,
+    /// Draw a block over the cursor cell
+    Block,
+    /// Draw a line under the cursor cell
+    Underline,
+    /// Draw a bar on the left of the cursor cell
+    Bar,
+}
+
+/// Rendering choices that do not depend on the window decoration
+#[derive(Clone, Debug, Default)]
+pub struct RenderOptions {
+    /// How hyperlinks are drawn
+    pub link_style: LinkStyle,
+    /// How shell prompts are drawn
+    pub prompt_style: PromptStyle,
+    /// How blinking text is drawn
+    pub blink_style: BlinkStyle,
+    /// How the cursor is drawn
+    pub cursor_style: CursorStyle,
+    /// Progress drawn along the title bar
+    pub progress: TaskProgress,
+    /// Title bar text, instead of the title set by the program or the command
+    pub title: Option<String>,
+}
+
 /// `ImageRenderer` is responsible for rendering a `ScreenBuilder` into an image
 /// using the provided window decoration and rendering metrics.
 #[derive(Debug)]
 pub struct ImageRenderer {
     canvas: Canvas,
+    /// Prompt and command drawn above the output, empty without a command
+    command_line: Vec<Cell>,
     metrics: WindowMetrics,
     window_decoration: Box<dyn WindowDecoration>,
     theme: Theme,
+    options: RenderOptions,
 }
 
 impl ImageRenderer {
@@ -47,6 +121,7 @@ impl ImageRenderer {
     ///
     /// * `screen` - The screen content to render.
     /// * `window_decoration` - A boxed `WindowDecoration` implementation to draw window chrome.
+    /// * `options` - Rendering choices such as the hyperlink style.
     ///
     /// # Returns
     ///
@@ -63,8 +138,10 @@ impl ImageRenderer {
         screen: &Surface,
         window_decoration: Box<dyn WindowDecoration>,
         theme: Theme,
+        options: RenderOptions,
     ) -> Result<RgbaImage, ImageRendererError> {
-        let mut renderer = Self::create_renderer(command, screen, window_decoration, theme)?;
+        let mut renderer =
+            Self::create_renderer(command, screen, window_decoration, theme, options)?;
         renderer.compose_image(command, screen)
     }
 
@@ -73,12 +150,17 @@ impl ImageRenderer {
         screen: &Surface,
         window_decoration: Box<dyn WindowDecoration>,
         theme: Theme,
+        options: RenderOptions,
     ) -> Result<Self, ImageRendererError> {
         let font = window_decoration.font()?;
 
         let scale = PxScale::from((FONT_SIZE * IMAGE_QUALITY_MULTIPLIER) as f32);
         let char_size = calculate_char_size(&font.regular, scale);
-        let command_line = window_decoration.build_command_line(&command.join(" "));
+        let command_line = if command.is_empty() {
+            Vec::new()
+        } else {
+            window_decoration.build_command_line(&command.join(" "))
+        };
 
         let metrics = window_decoration.compute_metrics(char_size);
         let image_size = calculate_image_size(&command_line, screen, &metrics, char_size);
@@ -86,9 +168,11 @@ impl ImageRenderer {
 
         Ok(Self {
             canvas,
+            command_line,
             metrics,
             window_decoration,
             theme,
+            options,
         })
     }
 
@@ -100,7 +184,17 @@ impl ImageRenderer {
         self.window_decoration
             .draw_window(&mut self.canvas, &self.metrics, &self.theme)?;
 
-        self.draw_command_line(command)?;
+        let title = self.title(command, screen);
+        self.window_decoration
+            .draw_title(&mut self.canvas, &self.metrics, &self.theme, &title)?;
+        self.window_decoration.draw_progress(
+            &mut self.canvas,
+            &self.metrics,
+            &self.theme,
+            self.options.progress,
+        )?;
+
+        self.draw_command_line()?;
 
         self.draw_terminal_content(screen)?;
 
@@ -111,18 +205,43 @@ impl ImageRenderer {
         Ok(final_image)
     }
 
-    fn draw_command_line(&mut self, command: &[String]) -> Result<(), ImageRendererError> {
+    /// The `--title` override, else the last title the program set, else the command.
+    fn title(&self, command: &[String], screen: &Surface) -> String {
+        self.options
+            .title
+            .clone()
+            .or_else(|| Some(screen.title().to_string()).filter(|title| !title.is_empty()))
+            .unwrap_or_else(|| command.join(" "))
+    }
+
+    /// Shape of the cursor to draw, `CursorStyle::None` when the cursor is
+    /// not drawn or was hidden by the program (DECTCEM).
+    fn cursor_style(&self, screen: &Surface) -> CursorStyle {
+        if screen.cursor_visibility() == CursorVisibility::Hidden {
+            return CursorStyle::None;
+        }
+        match self.options.cursor_style {
+            CursorStyle::Auto => match screen.cursor_shape().unwrap_or_default() {
+                CursorShape::BlinkingUnderline | CursorShape::SteadyUnderline => {
+                    CursorStyle::Underline
+                }
+                CursorShape::BlinkingBar | CursorShape::SteadyBar => CursorStyle::Bar,
+                CursorShape::Default | CursorShape::BlinkingBlock | CursorShape::SteadyBlock => {
+                    CursorStyle::Block
+                }
+            },
+            style => style,
+        }
+    }
+
+    fn draw_command_line(&mut self) -> Result<(), ImageRendererError> {
         let start_x = self.metrics.border_width + self.metrics.padding;
         let start_y =
             self.metrics.border_width + self.metrics.title_bar_height + self.metrics.padding;
 
-        let command_line = self
-            .window_decoration
-            .build_command_line(&command.join(" "));
-
         let y = i32::try_from(start_y)?;
         let mut x_offset = 0;
-        for cell in &command_line {
+        for cell in &self.command_line {
             let x = i32::try_from(start_x + x_offset)?;
 
             let text = cell.str();
@@ -144,8 +263,12 @@ impl ImageRenderer {
         let start_y =
             self.metrics.border_width + self.metrics.title_bar_height + self.metrics.padding;
 
-        for (row_idx, line) in screen.screen_lines().iter().enumerate() {
-            let row_idx = u32::try_from(row_idx + 1)?;
+        let cursor_style = self.cursor_style(screen);
+        let cursor_position = screen.cursor_position();
+
+        let first_row = usize::from(!self.command_line.is_empty());
+        for (row, line) in screen.screen_lines().iter().enumerate() {
+            let row_idx = u32::try_from(first_row + row)?;
             let y = i32::try_from(start_y + row_idx * self.canvas.char_height())?;
 
             let mut x_offset = 0;
@@ -154,7 +277,49 @@ impl ImageRenderer {
 
                 let text = cell.str();
 
-                self.canvas.draw_text(text, x, y, &self.theme, cell.attrs());
+                let mut attrs = cell.attrs().clone();
+                if self.options.link_style == LinkStyle::Underline
+                    && attrs.hyperlink().is_some()
+                    && attrs.underline() == Underline::None
+                {
+                    attrs.set_underline(Underline::Single);
+                }
+                if attrs.semantic_type() == SemanticType::Prompt {
+                    match self.options.prompt_style {
+                        PromptStyle::Plain => (),
+                        PromptStyle::Bold => {
+                            attrs.set_intensity(Intensity::Bold);
+                        }
+                        PromptStyle::Dim => {
+                            attrs.set_intensity(Intensity::Half);
+                        }
+                    }
+                }
+                if attrs.blink() != Blink::None {
+                    match self.options.blink_style {
+                        BlinkStyle::Visible => (),
+                        BlinkStyle::Hidden => {
+                            attrs.set_invisible(true);
+                        }
+                        BlinkStyle::Dim => {
+                            attrs.set_intensity(Intensity::Half);
+                        }
+                    }
+                }
+                let cursor = (cell.cell_index(), row) == cursor_position;
+                if cursor && cursor_style == CursorStyle::Block {
+                    attrs.set_reverse(false);
+                    attrs.set_background(rgba_color(self.theme.cursor_color));
+                    attrs.set_foreground(rgba_color(self.theme.background_color));
+                }
+                self.canvas.draw_text(text, x, y, &self.theme, &attrs);
+                for image in attrs.images().unwrap_or_default() {
+                    self.canvas.draw_image_cell(x, y, &image);
+                }
+                if cursor {
+                    self.canvas
+                        .draw_cursor(cursor_style, x, y, self.theme.cursor_color);
+                }
 
                 let text_width = text
                     .chars()
@@ -168,6 +333,10 @@ impl ImageRenderer {
     }
 }
 
+fn rgba_color(Rgba([r, g, b, a]): Rgba<u8>) -> ColorAttribute {
+    ColorAttribute::TrueColorWithDefaultFallback(SrgbaTuple::from((r, g, b, a)))
+}
+
 #[cfg(test)]
 mod tests {
     use termwiz::surface::Change;
@@ -191,7 +360,13 @@ mod tests {
 
         let command = vec!["echo".to_string(), "test".to_string()];
 
-        let result = ImageRenderer::render_image(&command, &surface, window_decoration, theme);
+        let result = ImageRenderer::render_image(
+            &command,
+            &surface,
+            window_decoration,
+            theme,
+            RenderOptions::default(),
+        );
 
         assert!(result.is_ok(), "ImageRenderer failed to render mock screen");
 
@@ -200,4 +375,23 @@ mod tests {
         assert!(image.width() > 0, "Rendered image width should be > 0");
         assert!(image.height() > 0, "Rendered image height should be > 0");
     }
+
+    #[test]
+    fn test_render_without_command_has_no_command_line() {
+        let render = |command: &[String]| {
+            ImageRenderer::render_image(
+                command,
+                &create_mock_surface(),
+                create_window_decoration(None),
+                Theme::default(),
+                RenderOptions::default(),
+            )
+            .expect("ImageRenderer failed to render mock screen")
+        };
+
+        let with_command = render(&["echo".to_string(), "test".to_string()]);
+        let without_command = render(&[]);
+
+        assert!(without_command.height() < with_command.height());
+    }
 }
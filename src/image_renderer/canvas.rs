@@ -1,6 +1,6 @@
 use crate::{
     image_renderer::{
-        ImageRendererError,
+        CursorStyle, ImageRendererError,
         render_size::{Size, calculate_char_size},
         utils::{
             resolve_background_color, resolve_foreground_color, resolve_rgba_with_palette,
@@ -16,9 +16,13 @@ use ab_glyph::ScaleFont;
 use image::{Rgba, RgbaImage};
 use imageproc::drawing::draw_text_mut;
 use termwiz::cell::{CellAttributes, Underline};
+use termwiz::image::{ImageCell, ImageDataType};
 use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Rect, Stroke, Transform};
 use tracing::warn;
 
+/// Points of a curly underline in each cell, where it makes one full wave
+const CURLY_STEPS_PER_CELL: u32 = 16;
+
 bitflags::bitflags! {
     pub struct Corners: u8 {
         const TOP_LEFT     = 0b0001;
@@ -32,6 +36,7 @@ bitflags::bitflags! {
 #[derive(Debug)]
 pub struct Canvas {
     background: Pixmap,
+    image_layer: RgbaImage,
     text_layer: RgbaImage,
     font: Fonts,
     scale: PxScale,
@@ -46,11 +51,13 @@ impl Canvas {
         scale: PxScale,
     ) -> Result<Self, ImageRendererError> {
         let background = Pixmap::new(width, height).ok_or(ImageRendererError::CanvasInitFailed)?;
+        let image_layer = RgbaImage::new(width, height);
         let text_layer = RgbaImage::new(width, height);
         let char_size = calculate_char_size(&font.regular, scale);
 
         Ok(Self {
             background,
+            image_layer,
             text_layer,
             font,
             scale,
@@ -223,15 +230,17 @@ impl Canvas {
         let fg_color = resolve_foreground_color(attributes, theme);
         let font = select_font(&self.font, attributes);
 
-        draw_text_mut(
-            &mut self.text_layer,
-            fg_color,
-            x,
-            y,
-            self.scale,
-            &font,
-            text,
-        );
+        if !attributes.invisible() {
+            draw_text_mut(
+                &mut self.text_layer,
+                fg_color,
+                x,
+                y,
+                self.scale,
+                &font,
+                text,
+            );
+        }
 
         self.draw_cell_attributes(text, x, y, &font, fg_color, theme, attributes);
     }
@@ -260,9 +269,18 @@ impl Canvas {
             resolve_rgba_with_palette(&theme.palette, attributes.underline_color())
                 .unwrap_or(fg_color);
 
-        if attributes.underline() != Underline::None {
-            let underline_y = scaled_font.descent().abs().mul_add(0.3, baseline);
-            self.fill_rect(x, underline_y as i32, width, thickness, underline_color);
+        let underline_y = scaled_font.descent().abs().mul_add(0.3, baseline) as i32;
+        self.draw_underline(
+            attributes.underline(),
+            x,
+            underline_y,
+            width,
+            thickness,
+            underline_color,
+        );
+
+        if attributes.overline() {
+            self.fill_rect(x, y, width, thickness, fg_color);
         }
 
         if attributes.strikethrough() {
@@ -272,6 +290,139 @@ impl Canvas {
         }
     }
 
+    /// Draws an underline of the given style whose top edge is at `y`. Dotted,
+    /// dashed and curly patterns repeat every cell, so that the cells of a
+    /// word join up.
+    fn draw_underline(
+        &mut self,
+        underline: Underline,
+        x: i32,
+        y: i32,
+        width: u32,
+        thickness: u32,
+        color: Rgba<u8>,
+    ) {
+        let cell_width = self.char_width();
+        let cells = 0..width.div_ceil(cell_width.max(1));
+        let cell_x = |cell: u32| x + (cell * cell_width) as i32;
+
+        match underline {
+            Underline::None => {}
+            Underline::Single => self.fill_rect(x, y, width, thickness, color),
+            Underline::Double => {
+                self.fill_rect(x, y - thickness as i32, width, thickness, color);
+                self.fill_rect(x, y + thickness as i32, width, thickness, color);
+            }
+            Underline::Dotted => {
+                let dots = (cell_width / (2 * thickness)).max(1);
+                let spacing = cell_width as f32 / dots as f32;
+                for cell in cells {
+                    for dot in 0..dots {
+                        let dot_x = cell_x(cell) + (dot as f32 * spacing) as i32;
+                        self.fill_rect(dot_x, y, thickness, thickness, color);
+                    }
+                }
+            }
+            Underline::Dashed => {
+                let gap = cell_width / 6;
+                for cell in cells {
+                    let dash_x = cell_x(cell) + gap as i32;
+                    self.fill_rect(dash_x, y, cell_width - 2 * gap, thickness, color);
+                }
+            }
+            Underline::Curly => {
+                let amplitude = thickness as f32;
+                let center = y as f32 + thickness as f32 / 2.0;
+                let mut pb = PathBuilder::new();
+                pb.move_to(x as f32, center);
+                for cell in cells {
+                    let start = cell_x(cell) as f32;
+                    for step in 1..=CURLY_STEPS_PER_CELL {
+                        let t = step as f32 / CURLY_STEPS_PER_CELL as f32;
+                        let wave = (t * std::f32::consts::TAU).sin();
+                        pb.line_to(
+                            t.mul_add(cell_width as f32, start),
+                            amplitude.mul_add(-wave, center),
+                        );
+                    }
+                }
+                let Some(path) = pb.finish() else {
+                    return;
+                };
+
+                let mut paint = Paint::default();
+                paint.set_color(Color::from_rgba8(color[0], color[1], color[2], color[3]));
+                paint.anti_alias = true;
+                let stroke = Stroke {
+                    width: thickness as f32,
+                    ..Default::default()
+                };
+                self.background
+                    .stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+            }
+        }
+    }
+
+    /// Draws an underline or bar cursor over the cell at `x`, `y`. Block
+    /// cursors are drawn by swapping the colors of the cell instead.
+    pub fn draw_cursor(&mut self, style: CursorStyle, x: i32, y: i32, color: Rgba<u8>) {
+        let (width, height) = (self.char_width(), self.char_height());
+        match style {
+            CursorStyle::Underline => {
+                let thickness = (height / 10).max(1);
+                self.fill_rect(x, y + (height - thickness) as i32, width, thickness, color);
+            }
+            CursorStyle::Bar => self.fill_rect(x, y, (width / 8).max(1), height, color),
+            CursorStyle::None | CursorStyle::Auto | CursorStyle::Block => {}
+        }
+    }
+
+    /// Draws the slice of an image attached to the cell at `x`, `y`, scaled to the cell.
+    pub fn draw_image_cell(&mut self, x: i32, y: i32, image: &ImageCell) {
+        let data = image.image_data().data();
+        let ImageDataType::Rgba8 {
+            data,
+            width,
+            height,
+            ..
+        } = &*data
+        else {
+            return;
+        };
+
+        let (top_left, bottom_right) = (image.top_left(), image.bottom_right());
+        let (cell_width, cell_height) = (self.char_width(), self.char_height());
+        let scale_x = (bottom_right.x - top_left.x).into_inner() / cell_width as f32;
+        let scale_y = (bottom_right.y - top_left.y).into_inner() / cell_height as f32;
+
+        for dy in 0..cell_height {
+            let v = (dy as f32 + 0.5).mul_add(scale_y, top_left.y.into_inner());
+            let src_y = (v * *height as f32).floor();
+            let target_y = y + dy as i32;
+            if src_y < 0.0 || src_y >= *height as f32 || target_y < 0 {
+                continue;
+            }
+
+            for dx in 0..cell_width {
+                let u = (dx as f32 + 0.5).mul_add(scale_x, top_left.x.into_inner());
+                let src_x = (u * *width as f32).floor();
+                let target_x = x + dx as i32;
+                if src_x < 0.0 || src_x >= *width as f32 || target_x < 0 {
+                    continue;
+                }
+
+                let idx = (src_y as usize * *width as usize + src_x as usize) * 4;
+                if let (Some(&[r, g, b, a]), Some(target)) = (
+                    data.get(idx..idx + 4),
+                    self.image_layer
+                        .get_pixel_mut_checked(target_x as u32, target_y as u32),
+                ) {
+                    *target = Rgba([r, g, b, a]);
+                }
+            }
+        }
+    }
+
     pub fn width(&self) -> u32 {
         self.background.width()
     }
@@ -296,15 +447,9 @@ impl Canvas {
         )
         .ok_or(ImageRendererError::ImageCreationFailed)?;
 
-        for (final_pixel, text_pixel) in final_image.pixels_mut().zip(self.text_layer.pixels()) {
-            let alpha = text_pixel[3] as f32 / 255.0;
-            if alpha > 0.0 {
-                for i in 0..3 {
-                    final_pixel[i] = (text_pixel[i] as f32)
-                        .mul_add(alpha, final_pixel[i] as f32 * (1.0 - alpha))
-                        as u8;
-                }
-                final_pixel[3] = 255;
+        for layer in [&self.image_layer, &self.text_layer] {
+            for (final_pixel, layer_pixel) in final_image.pixels_mut().zip(layer.pixels()) {
+                blend_pixel(final_pixel, *layer_pixel);
             }
         }
 
@@ -312,6 +457,17 @@ impl Canvas {
     }
 }
 
+/// Blends a layer `pixel` over the opaque `target`.
+fn blend_pixel(target: &mut Rgba<u8>, pixel: Rgba<u8>) {
+    let alpha = pixel[3] as f32 / 255.0;
+    if alpha > 0.0 {
+        for i in 0..3 {
+            target[i] = (pixel[i] as f32).mul_add(alpha, target[i] as f32 * (1.0 - alpha)) as u8;
+        }
+        target[3] = 255;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::window_decoration::common::default_font;
@@ -365,6 +521,76 @@ mod tests {
         assert_eq!(img.height(), 60);
     }
 
+    #[test]
+    fn underline_styles_differ() {
+        let color = Rgba([255, 0, 0, 255]);
+        let draw = |underline| {
+            let mut c = Canvas::new(100, 40, make_font(), 20.0.into()).unwrap();
+            c.draw_underline(underline, 0, 20, 100, 2, color);
+            c.to_final_image().unwrap()
+        };
+        let painted = |img: &RgbaImage| img.pixels().filter(|p| p[0] > 128).count();
+
+        let single = draw(Underline::Single);
+        let dotted = draw(Underline::Dotted);
+        let dashed = draw(Underline::Dashed);
+        let double = draw(Underline::Double);
+        let curly = draw(Underline::Curly);
+
+        assert_eq!(painted(&single), 200);
+        assert!(painted(&dotted) < painted(&dashed) && painted(&dashed) < painted(&single));
+        assert_eq!(painted(&double), 400);
+        assert!(curly.get_pixel(0, 16)[0] == 0 && painted(&curly) > painted(&dotted));
+        assert!((0..100).any(|x| curly.get_pixel(x, 18)[0] > 128));
+        assert!((0..100).any(|x| curly.get_pixel(x, 23)[0] > 128));
+    }
+
+    #[test]
+    fn draw_cursor_shapes() {
+        let color = Rgba([255, 0, 0, 255]);
+        let draw = |style| {
+            let mut c = Canvas::new(100, 60, make_font(), 20.0.into()).unwrap();
+            c.draw_cursor(style, 0, 0, color);
+            c.to_final_image().unwrap()
+        };
+        let bar = draw(CursorStyle::Bar);
+        let underline = draw(CursorStyle::Underline);
+        let block = draw(CursorStyle::Block);
+
+        assert_eq!(bar.get_pixel(0, 1)[0], 255);
+        assert_eq!(bar.get_pixel(5, 1)[0], 0);
+        assert_eq!(underline.get_pixel(5, 1)[0], 0);
+        assert!((0..60).any(|y| underline.get_pixel(5, y)[0] == 255));
+        assert!(block.pixels().all(|p| p[0] == 0));
+    }
+
+    #[test]
+    fn draw_image_cell_scales_slice() {
+        use std::sync::Arc;
+        use termwiz::image::{ImageData, TextureCoordinate};
+
+        let font = make_font();
+        let mut c = Canvas::new(100, 60, font, 12.0.into()).unwrap();
+        c.fill(Rgba([0, 0, 0, 255]));
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let data = [red, blue].concat();
+        let image = Arc::new(ImageData::with_data(ImageDataType::new_single_frame(
+            2, 1, data,
+        )));
+        let cell = ImageCell::new(
+            TextureCoordinate::new_f32(0.5, 0.0),
+            TextureCoordinate::new_f32(1.0, 1.0),
+            image,
+        );
+
+        c.draw_image_cell(10, 10, &cell);
+
+        let img = c.to_final_image().unwrap();
+        assert_eq!(*img.get_pixel(10, 10), Rgba(blue));
+        assert_eq!(*img.get_pixel(9, 10), Rgba([0, 0, 0, 255]));
+    }
+
     #[test]
     fn final_image_has_correct_dimensions() {
         let font = make_font();
@@ -39,15 +39,18 @@ pub fn calculate_image_size(
     let mut content_height =
         screen_height as u32 * char_height + padding + border + metrics.title_bar_height;
 
-    let command_line_width: u32 = command_line
-        .iter()
-        .map(|cell| cell.str().chars().count() as u32)
-        .sum::<u32>()
-        * char_width
-        + padding
-        + border;
-    content_width = content_width.max(command_line_width);
-    content_height += char_height;
+    // The command line is left out when there is no command
+    if !command_line.is_empty() {
+        let command_line_width: u32 = command_line
+            .iter()
+            .map(|cell| cell.str().chars().count() as u32)
+            .sum::<u32>()
+            * char_width
+            + padding
+            + border;
+        content_width = content_width.max(command_line_width);
+        content_height += char_height;
+    }
 
     Size {
         width: content_width,
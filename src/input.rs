@@ -0,0 +1,59 @@
+use std::{
+    fs,
+    io::{self, Read},
+};
+
+/// Path that stands for the standard input
+pub const STDIN: &str = "-";
+
+/// Reads recorded terminal output from the file at `path`, or from the
+/// standard input when `path` is `-`.
+///
+/// # Errors
+///
+/// Returns an error if the input cannot be read.
+pub fn read_input(path: &str) -> io::Result<Vec<u8>> {
+    if path == STDIN {
+        let mut output = Vec::new();
+        io::stdin().lock().read_to_end(&mut output)?;
+        Ok(output)
+    } else {
+        fs::read(path)
+    }
+}
+
+/// Turns each line feed into CR LF, as a PTY does with the output of a
+/// command (`onlcr`). Files and pipes keep bare line feeds, which a terminal
+/// would draw as a staircase.
+pub fn translate_line_feeds(output: &[u8]) -> Vec<u8> {
+    let mut translated = Vec::with_capacity(output.len());
+    for &byte in output {
+        if byte == b'\n' {
+            translated.push(b'\r');
+        }
+        translated.push(byte);
+    }
+    translated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate_line_feeds() {
+        assert_eq!(translate_line_feeds(b"a\nb\r\n"), b"a\r\nb\r\r\n");
+        assert_eq!(translate_line_feeds(b""), b"");
+    }
+
+    #[test]
+    fn test_read_input_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("build.log");
+        fs::write(&path, b"\x1b[32mok\x1b[0m\n").unwrap();
+
+        let output = read_input(path.to_str().unwrap()).unwrap();
+        assert_eq!(output, b"\x1b[32mok\x1b[0m\n");
+        assert!(read_input(dir.path().join("missing").to_str().unwrap()).is_err());
+    }
+}
@@ -4,13 +4,18 @@
 #![doc = include_str!("../README.md")]
 
 mod app;
+mod asciicast;
 mod constants;
 mod image_generator;
 mod image_renderer;
+mod input;
 mod pty_executor;
 mod terminal_builder;
 mod theme;
 mod window_decoration;
 
 pub use app::{Args, run_shellshot};
+pub use asciicast::Frame;
+pub use image_renderer::{BlinkStyle, CursorStyle, LinkStyle, PromptStyle};
+pub use terminal_builder::{CommandOutput, LineSelection, ScreenBuffer};
 pub use window_decoration::WindowDecorationType;
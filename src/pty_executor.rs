@@ -1,24 +1,31 @@
 use portable_pty::{CommandBuilder, PtySize, native_pty_system};
+use regex::Regex;
 use std::{
     io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
     thread,
     time::Duration,
 };
-use termwiz::surface::Surface;
 use thiserror::Error;
 use tracing::info;
 
 use crate::{
+    asciicast::AsciicastWriter,
     constants::{SCREEN_MAX_HEIGHT, SCREEN_MAX_WIDTH},
     pty_executor::{
         dimension::Dimension,
-        utils::with_timeout,
+        input_script::InputScript,
+        screen_watch::ScreenWatch,
+        utils::{CaptureLimits, with_watchdog},
         writer::{DetachableWriter, ThreadedWriter},
     },
-    terminal_builder::TerminalBuilder,
+    terminal_builder::{Capture, CommandOutput, LineSelection, ScreenBuffer, TerminalBuilder},
+    theme::Theme,
 };
 
 pub mod dimension;
+pub mod input_script;
+pub mod screen_watch;
 mod utils;
 pub mod writer;
 
@@ -42,6 +49,9 @@ pub enum PtyExecutorError {
     #[error("Failed to take PTY writer: {0}")]
     TakeWriterFailed(String),
 
+    #[error("Failed to create recording: {0}")]
+    CreateRecordingFailed(String),
+
     #[error("Failed to join thread: {0}")]
     ThreadJoinFailed(String),
 
@@ -58,6 +68,10 @@ pub enum PtyExecutorError {
 pub struct PtyIO {
     pub reader: BufReader<Box<dyn Read + Send>>,
     pub writer: DetachableWriter,
+    /// Receives the screen as it is drawn, when something waits on it
+    pub watch: Option<ScreenWatch>,
+    /// Records the output as it is read
+    pub recorder: Option<AsciicastWriter>,
 }
 
 #[derive(Clone)]
@@ -65,8 +79,25 @@ pub struct PtyOptions {
     pub cols: Dimension,
     pub rows: Dimension,
     pub timeout: Option<Duration>,
+    /// Ends the capture once this pattern appears on the screen
+    pub until: Option<Regex>,
+    /// Ends the capture after this long without output
+    pub idle: Option<Duration>,
+    /// Ends the capture at this time since the start
+    pub capture_at: Option<Duration>,
+    pub screen: ScreenBuffer,
+    pub lines: LineSelection,
+    pub command_output: CommandOutput,
+    pub theme: Theme,
+    pub tab_width: usize,
     pub shell: bool,
     pub quiet: bool,
+    /// Whether the cursor is drawn, so that its cell is captured
+    pub show_cursor: bool,
+    /// Keystrokes typed into the command while it runs
+    pub script: Option<InputScript>,
+    /// Path of an asciicast recording of the session
+    pub record: Option<PathBuf>,
 }
 
 pub struct PtyExecutor {}
@@ -75,7 +106,7 @@ impl PtyExecutor {
     pub fn run_command(
         pty_options: &PtyOptions,
         command: &[String],
-    ) -> Result<Surface, PtyExecutorError> {
+    ) -> Result<Capture, PtyExecutorError> {
         if command.is_empty() {
             return Err(PtyExecutorError::EmptyCommand);
         }
@@ -135,29 +166,68 @@ impl PtyExecutor {
         let writer = ThreadedWriter::new(Box::new(BufWriter::new(writer)));
         let writer = DetachableWriter::new(Box::new(BufWriter::new(writer)));
 
+        let watch = (pty_options.script.is_some()
+            || pty_options.until.is_some()
+            || pty_options.idle.is_some())
+        .then(ScreenWatch::default);
+        let limits = CaptureLimits {
+            timeout: pty_options.timeout,
+            capture_at: pty_options.capture_at,
+            idle: pty_options.idle,
+        };
+        let recorder = pty_options
+            .record
+            .as_deref()
+            .map(|path| {
+                AsciicastWriter::create(
+                    path,
+                    pty_options.cols.to_u16(SCREEN_MAX_WIDTH),
+                    pty_options.rows.to_u16(SCREEN_MAX_HEIGHT),
+                    &command.join(" "),
+                )
+                .map_err(|e| PtyExecutorError::CreateRecordingFailed(e.to_string()))
+            })
+            .transpose()?;
         let pty_process = PtyIO {
             reader,
             writer: writer.clone(),
+            watch: watch.clone(),
+            recorder,
         };
-        let cols = &pty_options.cols;
-        let rows = &pty_options.rows;
-        let timeout = &pty_options.timeout;
-        let quiet = pty_options.quiet;
-
-        thread::scope(|s| -> Result<Surface, PtyExecutorError> {
-            let handle = s.spawn(|| TerminalBuilder::run(pty_process, cols, rows, quiet));
-
-            with_timeout(*timeout, killer, s, || child.wait())??;
-
-            writer.detach()?.flush()?;
+        thread::scope(|s| -> Result<Capture, PtyExecutorError> {
+            let handle = s.spawn(|| TerminalBuilder::run(pty_process, pty_options));
+            let script_handle =
+                pty_options
+                    .script
+                    .as_ref()
+                    .zip(watch.clone())
+                    .map(|(script, watch)| {
+                        let mut writer = writer.clone();
+                        s.spawn(move || script.run(&mut writer, &watch))
+                    });
+
+            with_watchdog(limits, watch, killer, s, || child.wait())??;
+
+            // Dropping the PTY writer sends a newline and EOF, which the PTY
+            // echoes back: keep it until the capture is done so that the
+            // cursor stays where the command left it.
+            let mut pty_writer = writer.detach()?;
+            pty_writer.flush()?;
             drop(child);
             drop(pair);
 
-            let surface = handle
+            let capture = handle
                 .join()
                 .map_err(|e| PtyExecutorError::ThreadJoinFailed(format!("{e:?}")))??;
+            drop(pty_writer);
+
+            if let Some(script_handle) = script_handle {
+                script_handle
+                    .join()
+                    .map_err(|e| PtyExecutorError::ThreadJoinFailed(format!("{e:?}")))??;
+            }
 
-            Ok(surface)
+            Ok(capture)
         })
     }
 }
@@ -165,6 +235,8 @@ impl PtyExecutor {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::asciicast::{Asciicast, Frame};
+    use crate::constants::TAB_WIDTH;
     use crate::pty_executor::dimension::Dimension;
     use std::time::Duration;
 
@@ -173,8 +245,19 @@ mod tests {
             cols: Dimension::Value(80),
             rows: Dimension::Value(24),
             timeout: Some(Duration::from_secs(5)),
+            until: None,
+            idle: None,
+            capture_at: None,
+            screen: ScreenBuffer::Primary,
+            lines: LineSelection::Screen,
+            command_output: CommandOutput::All,
+            theme: Theme::default(),
+            tab_width: TAB_WIDTH,
             shell: false,
             quiet: true,
+            show_cursor: false,
+            script: None,
+            record: None,
         }
     }
 
@@ -191,9 +274,9 @@ mod tests {
         let options = default_options();
         let command = shell_command("echo Hello World");
 
-        let surface = PtyExecutor::run_command(&options, &command).expect("Failed to run command");
+        let capture = PtyExecutor::run_command(&options, &command).expect("Failed to run command");
 
-        let text = surface.screen_chars_to_string();
+        let text = capture.surface.screen_chars_to_string();
         println!("Captured output:\n{text}");
         assert!(text.contains("Hello World"));
     }
@@ -210,10 +293,10 @@ mod tests {
 
         let command = shell_command(ansi_str);
 
-        let surface =
+        let capture =
             PtyExecutor::run_command(&options, &command).expect("Failed to run ANSI command");
 
-        let text = surface.screen_chars_to_string();
+        let text = capture.surface.screen_chars_to_string();
         println!("Captured ANSI output:\n{text}");
 
         assert!(text.contains("Red"));
@@ -221,6 +304,91 @@ mod tests {
         assert!(text.contains("Bold"));
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_script_types_into_command() {
+        let options = PtyOptions {
+            script: Some(
+                "wait name\\?\ntype shellshot\nkey Enter"
+                    .parse()
+                    .expect("script should parse"),
+            ),
+            ..default_options()
+        };
+        let command = shell_command("printf 'name? '; read name; echo \"hello $name\"");
+
+        let capture = PtyExecutor::run_command(&options, &command).expect("Failed to run command");
+
+        let text = capture.surface.screen_chars_to_string();
+        assert!(text.contains("name? shellshot"));
+        assert!(text.contains("hello shellshot"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_until_pattern_ends_capture() {
+        let options = PtyOptions {
+            timeout: Some(Duration::from_secs(10)),
+            until: Some(Regex::new("tick 2").expect("valid pattern")),
+            ..default_options()
+        };
+        let command = shell_command("for i in 1 2 3 4 5; do echo tick $i; sleep 1; done");
+
+        let start = std::time::Instant::now();
+        let capture = PtyExecutor::run_command(&options, &command).expect("Failed to run command");
+
+        let text = capture.surface.screen_chars_to_string();
+        assert!(text.contains("tick 2"));
+        assert!(!text.contains("tick 3"));
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_idle_ends_capture() {
+        let options = PtyOptions {
+            timeout: Some(Duration::from_secs(10)),
+            idle: Some(Duration::from_millis(300)),
+            ..default_options()
+        };
+        let command = shell_command("echo ready; sleep 10");
+
+        let start = std::time::Instant::now();
+        let capture = PtyExecutor::run_command(&options, &command).expect("Failed to run command");
+
+        assert!(capture.surface.screen_chars_to_string().contains("ready"));
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_record_session() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("session.cast");
+        let options = PtyOptions {
+            record: Some(path.clone()),
+            ..default_options()
+        };
+        let command = shell_command("echo first; sleep 0.3; echo second");
+
+        PtyExecutor::run_command(&options, &command).expect("Failed to run command");
+
+        let recording = std::fs::read(&path).expect("recording should be written");
+        let cast = Asciicast::parse(&recording).expect("recording should parse");
+        assert_eq!((cast.header.width, cast.header.height), (80, 24));
+        assert_eq!(
+            cast.header.title.as_deref(),
+            Some("sh -c echo first; sleep 0.3; echo second")
+        );
+        let before = cast
+            .output(&Frame::Time(Duration::from_millis(200)))
+            .expect("frame should exist");
+        let before = String::from_utf8_lossy(&before);
+        assert!(before.contains("first") && !before.contains("second"));
+        let end = cast.output(&Frame::End).expect("frame should exist");
+        assert!(String::from_utf8_lossy(&end).contains("second"));
+    }
+
     #[test]
     fn test_empty_command_error() {
         let options = default_options();
@@ -236,8 +404,19 @@ mod tests {
             cols: Dimension::Value(80),
             rows: Dimension::Value(24),
             timeout: Some(Duration::from_millis(500)),
+            until: None,
+            idle: None,
+            capture_at: None,
+            screen: ScreenBuffer::Primary,
+            lines: LineSelection::Screen,
+            command_output: CommandOutput::All,
+            theme: Theme::default(),
+            tab_width: TAB_WIDTH,
             shell: false,
             quiet: true,
+            show_cursor: false,
+            script: None,
+            record: None,
         };
 
         let command = if cfg!(windows) {
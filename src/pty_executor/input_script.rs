@@ -0,0 +1,346 @@
+use std::{
+    fs,
+    io::{self, Write},
+    str::FromStr,
+    time::Duration,
+};
+
+use regex::Regex;
+use termwiz::input::{KeyCode, KeyCodeEncodeModes, KeyboardEncoding, Modifiers};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::pty_executor::screen_watch::ScreenWatch;
+
+#[derive(Debug, Error)]
+pub enum InputScriptError {
+    #[error("Failed to read script: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Line {line}: unknown step `{step}`")]
+    UnknownStep { line: usize, step: String },
+
+    #[error("Line {line}: `{step}` needs an argument")]
+    MissingArgument { line: usize, step: String },
+
+    #[error("Line {line}: unknown key `{key}`")]
+    UnknownKey { line: usize, key: String },
+
+    #[error("Line {line}: invalid duration `{duration}`, expected e.g. `500ms` or `2s`")]
+    InvalidDuration { line: usize, duration: String },
+
+    #[error("Line {line}: invalid pattern: {source}")]
+    InvalidPattern { line: usize, source: regex::Error },
+}
+
+/// A key press, sent with the encoding of an xterm keyboard
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: Modifiers,
+}
+
+impl Key {
+    /// Parses names such as `Enter`, `Up`, `F5`, `Ctrl-C` or `Alt-Shift-Left`.
+    fn parse(name: &str) -> Option<Self> {
+        let mut modifiers = Modifiers::NONE;
+        let mut rest = name;
+        while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" => Modifiers::CTRL,
+                "alt" | "meta" => Modifiers::ALT,
+                "shift" => Modifiers::SHIFT,
+                _ => return None,
+            };
+            rest = key;
+        }
+
+        let code = match rest.to_ascii_lowercase().as_str() {
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "escape" | "esc" => KeyCode::Escape,
+            "backspace" => KeyCode::Backspace,
+            "space" => KeyCode::Char(' '),
+            "up" => KeyCode::UpArrow,
+            "down" => KeyCode::DownArrow,
+            "left" => KeyCode::LeftArrow,
+            "right" => KeyCode::RightArrow,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "insert" => KeyCode::Insert,
+            "delete" | "del" => KeyCode::Delete,
+            function if function.starts_with('f') && function.len() > 1 => KeyCode::Function(
+                function[1..]
+                    .parse()
+                    .ok()
+                    .filter(|n| (1..=12).contains(n))?,
+            ),
+            _ => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => KeyCode::Char(ch),
+                    _ => return None,
+                }
+            }
+        };
+
+        let key = Self { code, modifiers };
+        key.encode(false).ok().filter(|bytes| !bytes.is_empty())?;
+        Some(key)
+    }
+
+    fn encode(self, application_cursor_keys: bool) -> io::Result<String> {
+        let modes = KeyCodeEncodeModes {
+            encoding: KeyboardEncoding::Xterm,
+            application_cursor_keys,
+            newline_mode: false,
+            modify_other_keys: None,
+        };
+        self.code
+            .encode(self.modifiers, modes, true)
+            .map_err(|err| io::Error::other(err.to_string()))
+    }
+}
+
+/// One step of an input script
+#[derive(Clone, Debug)]
+pub enum InputStep {
+    /// Text typed as-is
+    Text(String),
+    /// Named keys pressed one after the other
+    Keys(Vec<Key>),
+    /// Pause before the next step
+    Sleep(Duration),
+    /// Wait until the pattern matches the screen
+    WaitFor(Regex),
+}
+
+/// Keystrokes typed into the command while it runs, read from a script with
+/// one step per line:
+///
+/// ```text
+/// # Lines starting with # are comments
+/// wait \$$
+/// type git add -p
+/// key Enter
+/// sleep 500ms
+/// key Down Down Ctrl-C
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct InputScript {
+    steps: Vec<InputStep>,
+}
+
+impl InputScript {
+    /// Reads the script at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or a step is invalid.
+    pub fn load(path: &str) -> Result<Self, InputScriptError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Plays the steps, writing to the command through `writer`. Stops early
+    /// when the output ends, since nobody is left to read the input.
+    pub fn run(&self, writer: &mut dyn Write, watch: &ScreenWatch) -> io::Result<()> {
+        for step in &self.steps {
+            match step {
+                InputStep::Text(text) => writer.write_all(text.as_bytes())?,
+                InputStep::Keys(keys) => {
+                    let application_cursor_keys = watch.application_cursor_keys();
+                    for key in keys {
+                        writer.write_all(key.encode(application_cursor_keys)?.as_bytes())?;
+                    }
+                }
+                InputStep::Sleep(duration) => {
+                    if !watch.sleep(*duration) {
+                        return Ok(());
+                    }
+                }
+                InputStep::WaitFor(pattern) => {
+                    if !watch.wait_for(pattern) {
+                        warn!("Output ended before `{pattern}` appeared on the screen");
+                        return Ok(());
+                    }
+                }
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for InputScript {
+    type Err = InputScriptError;
+
+    fn from_str(script: &str) -> Result<Self, Self::Err> {
+        let mut steps = Vec::new();
+        for (index, raw_line) in script.lines().enumerate() {
+            let line = index + 1;
+            let raw_line = raw_line.trim_start();
+            if raw_line.is_empty() || raw_line.starts_with('#') {
+                continue;
+            }
+
+            let (step, argument) = raw_line.split_once(' ').unwrap_or((raw_line, ""));
+            // Text is typed verbatim, other arguments ignore surrounding spaces
+            let trimmed = argument.trim();
+            if trimmed.is_empty() {
+                return Err(InputScriptError::MissingArgument {
+                    line,
+                    step: step.to_string(),
+                });
+            }
+
+            steps.push(match step {
+                "type" => InputStep::Text(argument.to_string()),
+                "key" => InputStep::Keys(
+                    trimmed
+                        .split_whitespace()
+                        .map(|name| {
+                            Key::parse(name).ok_or_else(|| InputScriptError::UnknownKey {
+                                line,
+                                key: name.to_string(),
+                            })
+                        })
+                        .collect::<Result<_, _>>()?,
+                ),
+                "sleep" => InputStep::Sleep(parse_duration(trimmed).ok_or_else(|| {
+                    InputScriptError::InvalidDuration {
+                        line,
+                        duration: trimmed.to_string(),
+                    }
+                })?),
+                "wait" => InputStep::WaitFor(
+                    Regex::new(trimmed)
+                        .map_err(|source| InputScriptError::InvalidPattern { line, source })?,
+                ),
+                _ => {
+                    return Err(InputScriptError::UnknownStep {
+                        line,
+                        step: step.to_string(),
+                    });
+                }
+            });
+        }
+
+        Ok(Self { steps })
+    }
+}
+
+/// Parses durations such as `250ms`, `2s` or `1.5s`; bare numbers are seconds.
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let (value, unit) = if let Some(millis) = duration.strip_suffix("ms") {
+        (millis, 0.001)
+    } else {
+        (duration.strip_suffix('s').unwrap_or(duration), 1.0)
+    };
+    let value: f64 = value.trim().parse().ok()?;
+    Duration::try_from_secs_f64(value * unit).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script: InputScript =
+            "# comment\n\nwait \\$$\ntype echo hi \nkey Enter Ctrl-C\nsleep 1.5s\n"
+                .parse()
+                .expect("script should parse");
+
+        let steps = &script.steps;
+        assert_eq!(steps.len(), 4);
+        assert!(matches!(&steps[0], InputStep::WaitFor(pattern) if pattern.as_str() == "\\$$"));
+        assert!(matches!(&steps[1], InputStep::Text(text) if text == "echo hi "));
+        assert!(matches!(&steps[2], InputStep::Keys(keys) if keys.len() == 2));
+        assert!(
+            matches!(&steps[3], InputStep::Sleep(duration) if *duration == Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            "press Enter".parse::<InputScript>(),
+            Err(InputScriptError::UnknownStep { line: 1, .. })
+        ));
+        assert!(matches!(
+            "\nkey Enter Hyper-X".parse::<InputScript>(),
+            Err(InputScriptError::UnknownKey { line: 2, .. })
+        ));
+        assert!(matches!(
+            "sleep soon".parse::<InputScript>(),
+            Err(InputScriptError::InvalidDuration { .. })
+        ));
+        assert!(matches!(
+            "wait (".parse::<InputScript>(),
+            Err(InputScriptError::InvalidPattern { .. })
+        ));
+        assert!(matches!(
+            "type".parse::<InputScript>(),
+            Err(InputScriptError::MissingArgument { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("3"), Some(Duration::from_secs(3)));
+        assert_eq!(parse_duration("-1s"), None);
+        assert_eq!(parse_duration("ms"), None);
+    }
+
+    #[test]
+    fn test_key_encoding() {
+        let encode = |name: &str, application_cursor_keys| {
+            Key::parse(name)
+                .expect("key should parse")
+                .encode(application_cursor_keys)
+                .expect("key should encode")
+        };
+
+        assert_eq!(encode("Enter", false), "\r");
+        assert_eq!(encode("ctrl-c", false), "\x03");
+        assert_eq!(encode("Alt-x", false), "\x1bx");
+        assert_eq!(encode("Up", false), "\x1b[A");
+        assert_eq!(encode("Up", true), "\x1bOA");
+        assert_eq!(encode("F1", false), "\x1bOP");
+        assert_eq!(Key::parse("F13"), None);
+        assert_eq!(Key::parse("Hyper-X"), None);
+    }
+
+    #[test]
+    fn test_run_waits_for_screen() {
+        let script: InputScript = "wait ready\ntype go\nkey Enter"
+            .parse()
+            .expect("script should parse");
+        let watch = ScreenWatch::default();
+        let mut written = Vec::new();
+
+        thread::scope(|s| {
+            let runner = s.spawn(|| script.run(&mut written, &watch));
+            watch.publish("ready", false);
+            runner.join().unwrap().expect("script should run");
+        });
+        assert_eq!(written, b"go\r");
+    }
+
+    #[test]
+    fn test_run_stops_when_output_ends() {
+        let script: InputScript = "wait never\ntype go".parse().expect("script should parse");
+        let watch = ScreenWatch::default();
+        watch.close();
+
+        let mut written = Vec::new();
+        script.run(&mut written, &watch).expect("script should run");
+        assert!(written.is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use regex::Regex;
+
+/// Latest screen of the capture, shared with the threads that wait on it
+#[derive(Clone, Debug, Default)]
+pub struct ScreenWatch {
+    inner: Arc<(Mutex<ScreenSnapshot>, Condvar)>,
+}
+
+#[derive(Debug, Default)]
+struct ScreenSnapshot {
+    text: String,
+    application_cursor_keys: bool,
+    /// When the screen was last published
+    last_output: Option<Instant>,
+    /// Whether the capture asked for the command to be stopped
+    end_requested: bool,
+    closed: bool,
+}
+
+impl ScreenWatch {
+    /// Replaces the screen text and wakes the waiting threads. Trailing blanks
+    /// are dropped, so that `$` matches right after the last character printed.
+    pub fn publish(&self, text: &str, application_cursor_keys: bool) {
+        let text = text
+            .lines()
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut snapshot = self.lock();
+        snapshot.text = text.trim_end().to_string();
+        snapshot.application_cursor_keys = application_cursor_keys;
+        snapshot.last_output = Some(Instant::now());
+        self.inner.1.notify_all();
+    }
+
+    /// Marks the end of the output: waits return from then on.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.inner.1.notify_all();
+    }
+
+    /// Asks for the command to be stopped, the screen being ready.
+    pub fn request_end(&self) {
+        self.lock().end_requested = true;
+    }
+
+    pub fn end_requested(&self) -> bool {
+        self.lock().end_requested
+    }
+
+    /// When the command last wrote to the screen.
+    pub fn last_output(&self) -> Option<Instant> {
+        self.lock().last_output
+    }
+
+    /// Whether `pattern` matches the screen.
+    pub fn is_match(&self, pattern: &Regex) -> bool {
+        pattern.is_match(&self.lock().text)
+    }
+
+    /// Whether the program asked for SS3 cursor keys (DECCKM).
+    pub fn application_cursor_keys(&self) -> bool {
+        self.lock().application_cursor_keys
+    }
+
+    /// Blocks until `pattern` matches the screen. Returns `false` when the
+    /// output ends first.
+    pub fn wait_for(&self, pattern: &Regex) -> bool {
+        let mut snapshot = self.lock();
+        loop {
+            if pattern.is_match(&snapshot.text) {
+                return true;
+            }
+            if snapshot.closed {
+                return false;
+            }
+            snapshot = self
+                .inner
+                .1
+                .wait(snapshot)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Sleeps for `duration`, or until the output ends. Returns `false` when
+    /// the output ended.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let mut snapshot = self.lock();
+        loop {
+            if snapshot.closed {
+                return false;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return true;
+            }
+            snapshot = self
+                .inner
+                .1
+                .wait_timeout(snapshot, remaining)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ScreenSnapshot> {
+        // The snapshot is replaced as a whole, a poisoned lock still holds a valid one
+        self.inner.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_wait_for_published_text() {
+        let watch = ScreenWatch::default();
+        let pattern = Regex::new(r"ready$").unwrap();
+
+        thread::scope(|s| {
+            let waiter = s.spawn(|| watch.wait_for(&pattern));
+            watch.publish("loading   \n     ", false);
+            watch.publish("loading   \nready   \n     ", true);
+            assert!(waiter.join().unwrap());
+        });
+        assert!(watch.application_cursor_keys());
+    }
+
+    #[test]
+    fn test_close_ends_waits() {
+        let watch = ScreenWatch::default();
+        let pattern = Regex::new("never").unwrap();
+
+        thread::scope(|s| {
+            let waiter = s.spawn(|| watch.wait_for(&pattern));
+            let sleeper = s.spawn(|| watch.sleep(Duration::from_secs(60)));
+            thread::sleep(Duration::from_millis(50));
+            watch.close();
+            assert!(!waiter.join().unwrap());
+            assert!(!sleeper.join().unwrap());
+        });
+    }
+
+    #[test]
+    fn test_sleep() {
+        let watch = ScreenWatch::default();
+        let start = Instant::now();
+        assert!(watch.sleep(Duration::from_millis(50)));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -4,16 +4,65 @@ use std::{
         atomic::{AtomicBool, Ordering},
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use portable_pty::ChildKiller;
-use tracing::warn;
+use tracing::{info, warn};
+
+use crate::pty_executor::{PtyExecutorError, screen_watch::ScreenWatch};
+
+/// How often the watchdog looks at the clock and the screen
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Limits after which the command is killed and the capture ends
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CaptureLimits {
+    /// Time after which the command is considered stuck
+    pub timeout: Option<Duration>,
+    /// Time at which the screen is captured
+    pub capture_at: Option<Duration>,
+    /// Time without output after which the screen is captured
+    pub idle: Option<Duration>,
+}
+
+impl CaptureLimits {
+    fn is_set(&self) -> bool {
+        self.timeout.is_some() || self.capture_at.is_some() || self.idle.is_some()
+    }
+
+    /// Why the command should be killed now, if it should.
+    fn reached(&self, start: Instant, watch: Option<&ScreenWatch>) -> Option<StopReason> {
+        let elapsed = start.elapsed();
+        let last_output = watch.and_then(ScreenWatch::last_output).unwrap_or(start);
+
+        if watch.is_some_and(ScreenWatch::end_requested) {
+            Some(StopReason::Pattern)
+        } else if self.timeout.is_some_and(|timeout| elapsed >= timeout) {
+            Some(StopReason::Timeout)
+        } else if self.capture_at.is_some_and(|at| elapsed >= at) {
+            Some(StopReason::CaptureTime)
+        } else if self.idle.is_some_and(|idle| last_output.elapsed() >= idle) {
+            Some(StopReason::Idle)
+        } else {
+            None
+        }
+    }
+}
 
-use crate::pty_executor::PtyExecutorError;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StopReason {
+    Timeout,
+    CaptureTime,
+    Idle,
+    Pattern,
+}
 
-pub fn with_timeout<'scope, R, F>(
-    timeout: Option<Duration>,
+/// Runs `f` while a watchdog thread kills the command once one of the
+/// `limits` is reached, or once the capture asks for it through `watch`.
+pub fn with_watchdog<'scope, R, F>(
+    limits: CaptureLimits,
+    watch: Option<ScreenWatch>,
     mut killer: Box<dyn ChildKiller + Send + Sync>,
     s: &'scope thread::Scope<'scope, '_>,
     f: F,
@@ -21,29 +70,40 @@ pub fn with_timeout<'scope, R, F>(
 where
     F: FnOnce() -> R,
 {
-    if let Some(timeout) = timeout {
-        let finished = Arc::new(AtomicBool::new(false));
-        let finished_clone = finished.clone();
+    if !limits.is_set() && watch.is_none() {
+        return Ok(f());
+    }
+
+    let finished = Arc::new(AtomicBool::new(false));
+    let finished_clone = finished.clone();
 
-        let t = s.spawn(move || {
-            thread::park_timeout(timeout);
-            if !finished_clone.load(Ordering::SeqCst) {
+    let t = s.spawn(move || {
+        let start = Instant::now();
+        while !finished_clone.load(Ordering::SeqCst) {
+            if let Some(reason) = limits.reached(start, watch.as_ref()) {
                 let _ = killer.kill();
-                warn!("Command execution was terminated due to timeout");
+                match reason {
+                    StopReason::Timeout => {
+                        warn!("Command execution was terminated due to timeout");
+                    }
+                    StopReason::CaptureTime => info!("Capture time reached, ending the capture"),
+                    StopReason::Idle => info!("Output went silent, ending the capture"),
+                    StopReason::Pattern => (),
+                }
+                return;
             }
-        });
+            thread::park_timeout(WATCHDOG_INTERVAL);
+        }
+    });
 
-        let result = f();
-        finished.store(true, Ordering::SeqCst);
+    let result = f();
+    finished.store(true, Ordering::SeqCst);
 
-        t.thread().unpark();
-        t.join()
-            .map_err(|e| PtyExecutorError::ThreadJoinFailed(format!("{e:?}")))?;
+    t.thread().unpark();
+    t.join()
+        .map_err(|e| PtyExecutorError::ThreadJoinFailed(format!("{e:?}")))?;
 
-        Ok(result)
-    } else {
-        Ok(f())
-    }
+    Ok(result)
 }
 
 #[cfg(test)]
@@ -85,19 +145,27 @@ mod tests {
         }
     }
 
+    fn timeout(millis: u64) -> CaptureLimits {
+        CaptureLimits {
+            timeout: Some(Duration::from_millis(millis)),
+            ..CaptureLimits::default()
+        }
+    }
+
     #[test]
     fn test_with_timeout_no_timeout() {
         let killer = Box::new(FakeKiller::new());
         let result =
-            thread::scope(|s| with_timeout(Some(Duration::from_millis(500)), killer, s, || 42))
-                .unwrap();
+            thread::scope(|s| with_watchdog(timeout(500), None, killer, s, || 42)).unwrap();
         assert_eq!(result, 42);
     }
 
     #[test]
     fn test_with_timeout_none() {
         let killer = Box::new(FakeKiller::new());
-        let result = thread::scope(|s| with_timeout(None, killer, s, || 123)).unwrap();
+        let result =
+            thread::scope(|s| with_watchdog(CaptureLimits::default(), None, killer, s, || 123))
+                .unwrap();
         assert_eq!(result, 123);
     }
 
@@ -110,7 +178,7 @@ mod tests {
 
         let start = std::time::Instant::now();
         let result = thread::scope(|s| {
-            with_timeout(Some(Duration::from_millis(200)), killer, s, || {
+            with_watchdog(timeout(200), None, killer, s, || {
                 thread::sleep(Duration::from_millis(500));
                 999
             })
@@ -122,11 +190,65 @@ mod tests {
         );
         assert!(
             result.is_ok(),
-            "with_timeout should still return result of f()"
+            "with_watchdog should still return result of f()"
         );
         assert!(
             start.elapsed() < Duration::from_millis(600),
             "Timeout should cut execution"
         );
     }
+
+    #[test]
+    fn test_idle_triggers_kill() {
+        let fake_killer = FakeKiller::new();
+        let killed_flag = fake_killer.killed.clone();
+        let limits = CaptureLimits {
+            idle: Some(Duration::from_millis(150)),
+            ..CaptureLimits::default()
+        };
+        let watch = ScreenWatch::default();
+
+        thread::scope(|s| {
+            with_watchdog(
+                limits,
+                Some(watch.clone()),
+                Box::new(fake_killer),
+                s,
+                || {
+                    // Output keeps the capture going...
+                    for _ in 0..6 {
+                        watch.publish("output", false);
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                    assert!(!killed_flag.load(Ordering::SeqCst));
+                    // ...until it stops
+                    thread::sleep(Duration::from_millis(300));
+                },
+            )
+        })
+        .unwrap();
+
+        assert!(killed_flag.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_end_request_triggers_kill() {
+        let fake_killer = FakeKiller::new();
+        let killed_flag = fake_killer.killed.clone();
+        let watch = ScreenWatch::default();
+        watch.request_end();
+
+        thread::scope(|s| {
+            with_watchdog(
+                CaptureLimits::default(),
+                Some(watch.clone()),
+                Box::new(fake_killer),
+                s,
+                || thread::sleep(Duration::from_millis(200)),
+            )
+        })
+        .unwrap();
+
+        assert!(killed_flag.load(Ordering::SeqCst));
+    }
 }
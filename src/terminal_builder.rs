@@ -1,18 +1,36 @@
+use clap::ValueEnum;
 use indicatif::style::TemplateError;
-use std::io::{self, BufRead};
+use regex::Regex;
+use std::io::{self, BufRead, BufReader, Cursor};
 use termwiz::color::ColorAttribute;
 use termwiz::escape::parser::Parser;
-use termwiz::surface::Surface;
+use termwiz::surface::{Change, CursorVisibility, Line, Position, Surface};
 use thiserror::Error;
+use tracing::{info, warn};
 
 use crate::constants::{SCREEN_MAX_HEIGHT, SCREEN_MAX_WIDTH};
-use crate::pty_executor::PtyIO;
 use crate::pty_executor::dimension::Dimension;
+use crate::pty_executor::writer::DetachableWriter;
+use crate::pty_executor::{PtyIO, PtyOptions};
 use crate::terminal_builder::action::process_action;
+pub use crate::terminal_builder::command_output::CommandOutput;
+pub use crate::terminal_builder::line_selection::LineSelection;
 use crate::terminal_builder::progress_bar::TerminalBuilderProgressBar;
+use crate::terminal_builder::state::TerminalState;
+pub use crate::terminal_builder::task_progress::TaskProgress;
+use crate::theme::Theme;
 
 mod action;
+mod charset;
+mod command_output;
+mod kitty;
+mod line_selection;
+mod modes;
 mod progress_bar;
+mod sixel;
+mod state;
+mod tab_stops;
+mod task_progress;
 mod utils;
 
 #[derive(Debug, Error)]
@@ -21,31 +39,89 @@ pub enum TerminalBuilderError {
     IoError(#[from] io::Error),
     #[error("Progress bar template error: {0}")]
     ProgressTemplateError(#[from] TemplateError),
+    #[error("Failed to write recording: {0}")]
+    RecordingFailed(io::Error),
+}
+
+/// Screen buffer of the terminal
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ScreenBuffer {
+    /// Main screen, restored when full-screen programs exit
+    #[default]
+    Primary,
+    /// Alternate screen used by full-screen programs, captured as its last frame
+    Alternate,
+}
+
+/// What the terminal holds once the command has ended
+pub struct Capture {
+    /// Selected lines of the terminal
+    pub surface: Surface,
+    /// Theme with the palette changes made by the command
+    pub theme: Theme,
+    /// Progress last reported by the command
+    pub progress: TaskProgress,
 }
 
 pub struct TerminalBuilder {
     pty_process: PtyIO,
     surface: Surface,
+    state: TerminalState,
     quiet: bool,
+    /// Whether the cursor is drawn, so that its cell is kept when resizing
+    show_cursor: bool,
+    /// Pattern that ends the capture when it appears on the screen
+    until: Option<Regex>,
 }
 
 impl TerminalBuilder {
-    pub fn run(
-        pty_process: PtyIO,
-        cols: &Dimension,
-        rows: &Dimension,
-        quiet: bool,
-    ) -> Result<Surface, TerminalBuilderError> {
+    /// Builds the screen from output recorded beforehand, without running a
+    /// command. Replies to the program's queries are dropped.
+    pub fn from_output(
+        output: Vec<u8>,
+        options: &PtyOptions,
+    ) -> Result<Capture, TerminalBuilderError> {
+        let pty_process = PtyIO {
+            reader: BufReader::new(Box::new(Cursor::new(output))),
+            writer: DetachableWriter::new(Box::new(io::sink())),
+            watch: None,
+            recorder: None,
+        };
+        Self::run(pty_process, options)
+    }
+
+    pub fn run(pty_process: PtyIO, options: &PtyOptions) -> Result<Capture, TerminalBuilderError> {
+        let (cols, rows) = (&options.cols, &options.rows);
         let mut terminal = Self {
             pty_process,
             surface: Surface::new(
                 cols.to_u16(SCREEN_MAX_WIDTH).into(),
                 rows.to_u16(SCREEN_MAX_HEIGHT).into(),
             ),
-            quiet,
+            state: TerminalState::new(options.theme.clone(), options.tab_width),
+            quiet: options.quiet,
+            show_cursor: options.show_cursor,
+            until: options.until.clone(),
         };
 
-        terminal.run_loop()?;
+        let result = terminal.run_loop();
+        if let Some(watch) = &terminal.pty_process.watch {
+            watch.close();
+        }
+        result?;
+        if let Some(recorder) = terminal.pty_process.recorder.take() {
+            recorder
+                .finish()
+                .map_err(TerminalBuilderError::RecordingFailed)?;
+        }
+        terminal.select_screen(options.screen);
+        terminal.apply_cursor_state();
+        terminal.select_lines(options.screen, &options.lines, options.command_output);
+        if let Some(title) = terminal.state.title() {
+            terminal
+                .surface
+                .add_change(Change::Title(title.to_string()));
+        }
         match (cols, rows) {
             (Dimension::Auto, Dimension::Auto) => terminal.resize_surface(true, true),
             (Dimension::Auto, Dimension::Value(_)) => terminal.resize_surface(true, false),
@@ -53,13 +129,18 @@ impl TerminalBuilder {
             (Dimension::Value(_), Dimension::Value(_)) => (),
         }
 
-        Ok(terminal.surface.clone())
+        Ok(Capture {
+            surface: terminal.surface.clone(),
+            theme: terminal.state.theme().clone(),
+            progress: terminal.state.progress(),
+        })
     }
 
     fn run_loop(&mut self) -> Result<Surface, TerminalBuilderError> {
         let reader = &mut self.pty_process.reader;
         let writer = &mut self.pty_process.writer;
         let surface = &mut self.surface;
+        let state = &mut self.state;
 
         let mut parser = Parser::new();
 
@@ -71,18 +152,40 @@ impl TerminalBuilder {
                 break;
             }
 
+            if let Some(recorder) = &mut self.pty_process.recorder {
+                recorder
+                    .output(buf)
+                    .map_err(TerminalBuilderError::RecordingFailed)?;
+            }
+
             let mut actions = Vec::new();
             parser.parse(buf, |action| action.append_to(&mut actions));
 
             for action in actions {
                 pb.update_progress(&action);
 
-                let seq = process_action(surface, writer, &action);
+                let seq = process_action(surface, state, writer, &action);
                 surface.flush_changes_older_than(seq);
             }
 
             let len = buf.len();
             reader.consume(len);
+
+            if let Some(watch) = &self.pty_process.watch {
+                watch.publish(
+                    &surface.screen_chars_to_string(),
+                    state.modes().application_cursor_keys,
+                );
+                let found = self
+                    .until
+                    .as_ref()
+                    .filter(|pattern| watch.is_match(pattern));
+                if let Some(pattern) = found {
+                    info!("`{pattern}` appeared on the screen, ending the capture");
+                    watch.request_end();
+                    break;
+                }
+            }
         }
 
         pb.finish();
@@ -90,6 +193,107 @@ impl TerminalBuilder {
         Ok(self.surface.clone())
     }
 
+    fn select_screen(&mut self, screen: ScreenBuffer) {
+        let surface = std::mem::take(&mut self.surface);
+        self.surface = self.state.take_screen(surface, screen);
+    }
+
+    /// Gives the surface the cursor shape and visibility of the terminal, which
+    /// are shared by both screen buffers.
+    fn apply_cursor_state(&mut self) {
+        let visibility = if self.state.modes().cursor_visible {
+            CursorVisibility::Visible
+        } else {
+            CursorVisibility::Hidden
+        };
+        self.surface
+            .add_change(Change::CursorShape(self.state.cursor_shape()));
+        self.surface
+            .add_change(Change::CursorVisibility(visibility));
+    }
+
+    /// Replaces the surface with the selected lines of the history, narrowed
+    /// to the output of one command when asked. The scrollback only belongs to
+    /// the primary screen.
+    fn select_lines(
+        &mut self,
+        screen: ScreenBuffer,
+        selection: &LineSelection,
+        command_output: CommandOutput,
+    ) {
+        let scrollback = match screen {
+            ScreenBuffer::Primary => self.state.take_scrollback(),
+            ScreenBuffer::Alternate => Default::default(),
+        };
+
+        if *selection == LineSelection::Screen {
+            if !scrollback.is_empty() {
+                warn!(
+                    "{} lines scrolled off the screen and are not captured, use `--lines all` to include them",
+                    scrollback.len()
+                );
+            }
+            if command_output == CommandOutput::All {
+                return;
+            }
+        }
+
+        let (cursor_x, cursor_y) = self.surface.cursor_position();
+        let cursor_row = scrollback.len() + cursor_y;
+        let mut history: Vec<Line> = scrollback.into_iter().collect();
+        let screen_lines = self.surface.screen_lines();
+        let mut screen_len = screen_lines
+            .iter()
+            .rposition(|line| content_width(line) > 0)
+            .map_or(0, |row| row + 1);
+        if self.show_cursor {
+            screen_len = screen_len.max(cursor_y + 1);
+        }
+        history.extend(
+            screen_lines
+                .into_iter()
+                .take(screen_len)
+                .map(|line| line.into_owned()),
+        );
+
+        let commands = match screen {
+            ScreenBuffer::Primary => self.state.command_lines(),
+            ScreenBuffer::Alternate => Vec::new(),
+        };
+        let mut range = selection.lines(history.len(), screen_len);
+        match command_output.lines(&commands, range.clone()) {
+            Some(command) => range = command,
+            None => warn!(
+                "No such command was found in the captured lines, capturing all of them. Commands are told apart by the OSC 133 marks of shell integration"
+            ),
+        }
+        let lines = &history[range.clone()];
+
+        let width = self.surface.dimensions().0;
+        let mut surface = Surface::new(width, lines.len());
+        for (row, line) in surface.screen_cells().into_iter().zip(lines) {
+            for cell in line.visible_cells() {
+                if let Some(target) = row.get_mut(cell.cell_index()) {
+                    *target = cell.as_cell();
+                }
+            }
+        }
+
+        surface.add_change(Change::CursorShape(
+            self.surface.cursor_shape().unwrap_or_default(),
+        ));
+        if range.contains(&cursor_row) {
+            surface.add_change(Change::CursorPosition {
+                x: Position::Absolute(cursor_x),
+                y: Position::Absolute(cursor_row - range.start),
+            });
+            surface.add_change(Change::CursorVisibility(self.surface.cursor_visibility()));
+        } else {
+            surface.add_change(Change::CursorVisibility(CursorVisibility::Hidden));
+        }
+        self.surface = surface;
+    }
+
     pub fn resize_surface(&mut self, resize_cols: bool, resize_rows: bool) {
         let lines = self.surface.screen_lines();
         let (current_cols, current_rows) = self.surface.dimensions();
@@ -98,14 +302,7 @@ impl TerminalBuilder {
         let mut max_row = 0;
 
         for (row_idx, line) in lines.iter().enumerate() {
-            let mut last_idx = 0;
-            for cell in line.visible_cells() {
-                let is_non_empty = !cell.str().chars().all(char::is_whitespace)
-                    || !matches!(cell.attrs().background(), ColorAttribute::Default);
-                if is_non_empty {
-                    last_idx = cell.cell_index() + 1;
-                }
-            }
+            let last_idx = content_width(line);
 
             if resize_cols {
                 max_col = max_col.max(last_idx);
@@ -116,6 +313,16 @@ impl TerminalBuilder {
             }
         }
 
+        if self.show_cursor && self.surface.cursor_visibility() == CursorVisibility::Visible {
+            let (cursor_x, cursor_y) = self.surface.cursor_position();
+            if resize_cols {
+                max_col = max_col.max(cursor_x + 1);
+            }
+            if resize_rows {
+                max_row = max_row.max(cursor_y + 1);
+            }
+        }
+
         let new_cols = if resize_cols { max_col } else { current_cols };
         let new_rows = if resize_rows { max_row } else { current_rows };
 
@@ -123,23 +330,68 @@ impl TerminalBuilder {
     }
 }
 
+/// Number of columns up to the last cell that is not blank.
+fn content_width(line: &Line) -> usize {
+    line.visible_cells()
+        .filter(|cell| {
+            !cell.str().chars().all(char::is_whitespace)
+                || !matches!(cell.attrs().background(), ColorAttribute::Default)
+                || cell.attrs().images().is_some()
+        })
+        .last()
+        .map_or(0, |cell| cell.cell_index() + 1)
+}
+
 #[cfg(test)]
 mod tests {
     use termwiz::{
         cell::AttributeChange,
-        surface::{Change, Position},
+        surface::{Change, CursorShape, Position},
     };
 
     use super::*;
-    use crate::pty_executor::PtyIO;
-    use crate::pty_executor::writer::DetachableWriter;
-    use std::io::{self, BufReader, Cursor};
+    use crate::constants::TAB_WIDTH;
+
+    fn options(cols: u16, rows: u16) -> PtyOptions {
+        PtyOptions {
+            cols: Dimension::Value(cols),
+            rows: Dimension::Value(rows),
+            timeout: None,
+            until: None,
+            idle: None,
+            capture_at: None,
+            screen: ScreenBuffer::Primary,
+            lines: LineSelection::Screen,
+            command_output: CommandOutput::All,
+            theme: Theme::default(),
+            tab_width: TAB_WIDTH,
+            shell: false,
+            quiet: true,
+            show_cursor: false,
+            script: None,
+            record: None,
+        }
+    }
 
     fn create_mock_pty(content: &[u8]) -> PtyIO {
         let cursor: Box<dyn io::Read + Send> = Box::new(Cursor::new(content.to_vec()));
         let reader = BufReader::new(cursor);
         let writer = DetachableWriter::new(Box::new(io::sink()));
-        PtyIO { reader, writer }
+        PtyIO {
+            reader,
+            writer,
+            watch: None,
+            recorder: None,
+        }
+    }
+
+    #[test]
+    fn test_terminal_builder_from_output() {
+        let surface = TerminalBuilder::from_output(b"one\r\n\x1b[31mtwo".to_vec(), &options(5, 3))
+            .expect("TerminalBuilder should run")
+            .surface;
+
+        assert_eq!(surface.screen_chars_to_string(), "one  \ntwo  \n     \n");
     }
 
     #[test]
@@ -147,13 +399,9 @@ mod tests {
         let content = b"Hello, Terminal!";
         let pty_process = create_mock_pty(content);
 
-        let surface = TerminalBuilder::run(
-            pty_process,
-            &Dimension::Value(10),
-            &Dimension::Value(5),
-            true,
-        )
-        .expect("TerminalBuilder should run");
+        let surface = TerminalBuilder::run(pty_process, &options(10, 5))
+            .expect("TerminalBuilder should run")
+            .surface;
 
         let (cols, rows) = surface.dimensions();
         assert_eq!(cols, 10);
@@ -172,7 +420,10 @@ mod tests {
         let mut builder = TerminalBuilder {
             pty_process,
             surface: Surface::new(5, 5),
+            state: TerminalState::default(),
             quiet: true,
+            show_cursor: false,
+            until: None,
         };
 
         let result = builder.run_loop();
@@ -202,7 +453,10 @@ mod tests {
         let mut builder = TerminalBuilder {
             pty_process: create_mock_pty(b""),
             surface,
+            state: TerminalState::default(),
             quiet: true,
+            show_cursor: false,
+            until: None,
         };
 
         builder.resize_surface(true, true);
@@ -246,7 +500,10 @@ mod tests {
         let mut builder = TerminalBuilder {
             pty_process: create_mock_pty(b""),
             surface,
+            state: TerminalState::default(),
             quiet: true,
+            show_cursor: false,
+            until: None,
         };
 
         builder.resize_surface(true, true);
@@ -256,4 +513,178 @@ mod tests {
         assert_eq!(new_cols, 3, "Expected 3 columns");
         assert_eq!(new_rows, 3, "Expected 3 rows");
     }
+
+    #[test]
+    fn test_terminal_builder_selects_screen_buffer() {
+        let content = b"shell\x1b[?1049h\x1b[Htui\x1b[?1049l";
+
+        let primary = TerminalBuilder::run(create_mock_pty(content), &options(10, 2))
+            .expect("TerminalBuilder should run")
+            .surface;
+        assert!(primary.screen_chars_to_string().starts_with("shell"));
+
+        let alternate_options = PtyOptions {
+            screen: ScreenBuffer::Alternate,
+            ..options(10, 2)
+        };
+        let alternate = TerminalBuilder::run(create_mock_pty(content), &alternate_options)
+            .expect("TerminalBuilder should run")
+            .surface;
+        assert!(alternate.screen_chars_to_string().starts_with("tui"));
+    }
+
+    fn run_lines(content: &[u8], rows: u16, lines: &LineSelection) -> Surface {
+        run_commands(content, rows, lines, CommandOutput::All)
+    }
+
+    fn run_commands(
+        content: &[u8],
+        rows: u16,
+        lines: &LineSelection,
+        command_output: CommandOutput,
+    ) -> Surface {
+        let options = PtyOptions {
+            lines: lines.clone(),
+            command_output,
+            ..options(5, rows)
+        };
+        TerminalBuilder::run(create_mock_pty(content), &options)
+            .expect("TerminalBuilder should run")
+            .surface
+    }
+
+    fn rows(surface: &Surface) -> Vec<String> {
+        surface
+            .screen_lines()
+            .iter()
+            .map(|line| line.as_str().trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_terminal_builder_keeps_scrollback() {
+        let content = b"1\r\n2\r\n3\r\n4\r\n5";
+
+        let screen = run_lines(content, 2, &LineSelection::Screen);
+        assert_eq!(rows(&screen), ["4", "5"]);
+
+        let all = run_lines(content, 2, &LineSelection::All);
+        assert_eq!(rows(&all), ["1", "2", "3", "4", "5"]);
+
+        let last = run_lines(content, 2, &LineSelection::Last(3));
+        assert_eq!(rows(&last), ["3", "4", "5"]);
+
+        let range = run_lines(content, 2, &LineSelection::Range { first: 2, last: 3 });
+        assert_eq!(rows(&range), ["2", "3"]);
+    }
+
+    #[test]
+    fn test_terminal_builder_skips_blank_screen_rows() {
+        let all = run_lines(b"1\r\n2", 5, &LineSelection::All);
+        assert_eq!(rows(&all), ["1", "2"]);
+    }
+
+    #[test]
+    fn test_erase_scrollback_clears_history() {
+        let all = run_lines(b"1\r\n2\r\n3\x1b[3J", 2, &LineSelection::All);
+        assert_eq!(rows(&all), ["2", "3"]);
+    }
+
+    #[test]
+    fn test_terminal_builder_keeps_window_title() {
+        let surface = run_lines(b"\x1b]2;my title\x07text", 2, &LineSelection::All);
+        assert_eq!(surface.title(), "my title");
+    }
+
+    #[test]
+    fn test_terminal_builder_selects_command_output() {
+        let content =
+            b"\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n\x1b]133;C\x07a\r\nb\r\n\x1b]133;D;0\x07\
+            \x1b]133;A\x07$ \x1b]133;B\x07pwd\r\n\x1b]133;C\x07/tmp\r\n\x1b]133;D;0\x07\
+            \x1b]133;A\x07$ \x1b]133;B\x07";
+
+        let first = run_commands(content, 3, &LineSelection::All, CommandOutput::Nth(1));
+        assert_eq!(rows(&first), ["a", "b"]);
+
+        let last = run_commands(content, 3, &LineSelection::All, CommandOutput::Last);
+        assert_eq!(rows(&last), ["/tmp"]);
+
+        let on_screen = run_commands(content, 3, &LineSelection::Screen, CommandOutput::Last);
+        assert_eq!(rows(&on_screen), ["/tmp"]);
+    }
+
+    #[test]
+    fn test_terminal_builder_counts_commands_without_output() {
+        let content = b"\x1b]133;A\x07$ \x1b]133;B\x07cd /tmp\r\n\x1b]133;C\x07\x1b]133;D;0\x07\
+            \x1b]133;A\x07$ \x1b]133;B\x07pwd\r\n\x1b]133;C\x07/tmp\r\n\x1b]133;D;0\x07\
+            \x1b]133;A\x07$ \x1b]133;B\x07";
+
+        let first = run_commands(content, 3, &LineSelection::All, CommandOutput::Nth(1));
+        assert!(rows(&first).is_empty());
+
+        let second = run_commands(content, 3, &LineSelection::All, CommandOutput::Nth(2));
+        assert_eq!(rows(&second), ["/tmp"]);
+
+        let last = run_commands(content, 3, &LineSelection::All, CommandOutput::Last);
+        assert_eq!(rows(&last), ["/tmp"]);
+    }
+
+    #[test]
+    fn test_terminal_builder_without_marks_keeps_every_line() {
+        let all = run_commands(b"1\r\n2", 2, &LineSelection::All, CommandOutput::Last);
+        assert_eq!(rows(&all), ["1", "2"]);
+    }
+
+    #[test]
+    fn test_terminal_builder_keeps_palette_changes() {
+        let capture = TerminalBuilder::run(
+            create_mock_pty(b"\x1b]4;1;rgb:12/34/56\x07text"),
+            &options(5, 2),
+        )
+        .expect("TerminalBuilder should run");
+
+        assert_eq!(
+            capture.theme.palette[1],
+            image::Rgba([0x12, 0x34, 0x56, 0xff])
+        );
+        assert_eq!(capture.theme.palette[2], Theme::default().palette[2]);
+    }
+
+    fn run_with_cursor(content: &[u8], lines: LineSelection) -> Surface {
+        let options = PtyOptions {
+            cols: Dimension::Auto,
+            rows: Dimension::Auto,
+            lines,
+            show_cursor: true,
+            ..options(10, 3)
+        };
+        TerminalBuilder::run(create_mock_pty(content), &options)
+            .expect("TerminalBuilder should run")
+            .surface
+    }
+
+    #[test]
+    fn test_terminal_builder_keeps_cursor() {
+        let screen = run_with_cursor(b"1\r\nName: \x1b[6 q", LineSelection::Screen);
+        assert_eq!(screen.cursor_position(), (6, 1));
+        assert_eq!(screen.dimensions(), (7, 2));
+        assert_eq!(screen.cursor_shape(), Some(CursorShape::SteadyBar));
+        assert_eq!(screen.cursor_visibility(), CursorVisibility::Visible);
+
+        let scrolled = run_with_cursor(b"1\r\n2\r\n3\r\n4\r\n5", LineSelection::All);
+        assert_eq!(scrolled.cursor_position(), (1, 4));
+
+        let first = run_with_cursor(
+            b"1\r\n2\r\n3\r\n4\r\n5",
+            LineSelection::Range { first: 1, last: 2 },
+        );
+        assert_eq!(first.cursor_visibility(), CursorVisibility::Hidden);
+    }
+
+    #[test]
+    fn test_terminal_builder_honors_hidden_cursor() {
+        let screen = run_with_cursor(b"text\x1b[?25l", LineSelection::Screen);
+        assert_eq!(screen.cursor_visibility(), CursorVisibility::Hidden);
+        assert_eq!(screen.dimensions(), (4, 1));
+    }
 }
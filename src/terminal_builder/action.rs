@@ -6,37 +6,45 @@ use termwiz::{
 };
 use tracing::debug;
 
-use crate::terminal_builder::action::{
-    control::process_control,
-    csi::process_csi,
-    operating_system_command::process_operating_system_command,
-    print::{process_print, process_print_string},
+use crate::terminal_builder::{
+    action::{
+        control::process_control,
+        csi::process_csi,
+        esc::process_esc,
+        graphics::{process_kitty_image, process_sixel},
+        operating_system_command::process_operating_system_command,
+        print::{process_print, process_print_string},
+    },
+    state::TerminalState,
 };
 
 mod control;
 mod csi;
+mod esc;
+mod graphics;
 mod operating_system_command;
 mod print;
 
 pub fn process_action(
     surface: &mut Surface,
+    state: &mut TerminalState,
     writer: &mut dyn io::Write,
     action: &Action,
 ) -> SequenceNo {
     debug!("Processing action: {:?}", action);
 
     match action {
-        Action::Print(ch) => process_print(surface, *ch),
-        Action::PrintString(str) => process_print_string(surface, str),
-        Action::Control(control_code) => process_control(surface, *control_code),
+        Action::Print(ch) => process_print(surface, state, *ch),
+        Action::PrintString(str) => process_print_string(surface, state, str),
+        Action::Control(control_code) => process_control(surface, state, *control_code),
         Action::DeviceControl(_device_control_mode) => SEQ_ZERO,
         Action::OperatingSystemCommand(operating_system_command) => {
-            process_operating_system_command(surface, writer, operating_system_command)
+            process_operating_system_command(surface, state, writer, operating_system_command)
         }
-        Action::CSI(csi) => process_csi(surface, writer, csi),
-        Action::Esc(_esc) => SEQ_ZERO,
-        Action::Sixel(_sixel) => SEQ_ZERO,
+        Action::CSI(csi) => process_csi(surface, state, writer, csi),
+        Action::Esc(esc) => process_esc(surface, state, esc),
+        Action::Sixel(sixel) => process_sixel(surface, state, sixel),
         Action::XtGetTcap(_items) => SEQ_ZERO,
-        Action::KittyImage(_kitty_image) => SEQ_ZERO,
+        Action::KittyImage(kitty_image) => process_kitty_image(surface, state, writer, kitty_image),
     }
 }
@@ -3,15 +3,34 @@ use termwiz::{
     surface::{Change, Position, SEQ_ZERO, SequenceNo, Surface},
 };
 
-pub fn process_control(surface: &mut Surface, control_code: ControlCode) -> SequenceNo {
+use crate::terminal_builder::{action::esc::next_line, state::TerminalState};
+
+pub fn process_control(
+    surface: &mut Surface,
+    state: &mut TerminalState,
+    control_code: ControlCode,
+) -> SequenceNo {
     match control_code {
         ControlCode::LineFeed
         | ControlCode::VerticalTab
         | ControlCode::FormFeed
-        | ControlCode::CarriageReturn
-        | ControlCode::HorizontalTab
-        | ControlCode::ShiftOut
-        | ControlCode::ShiftIn
+        | ControlCode::IND => state.line_feed(surface),
+        ControlCode::RI => state.reverse_index(surface),
+        ControlCode::NEL => next_line(surface, state),
+        ControlCode::HorizontalTab => state.horizontal_tab(surface, 1),
+        ControlCode::HTS => {
+            state.set_tab_stop(surface);
+            SEQ_ZERO
+        }
+        ControlCode::ShiftOut => {
+            state.shift_out(true);
+            SEQ_ZERO
+        }
+        ControlCode::ShiftIn => {
+            state.shift_out(false);
+            SEQ_ZERO
+        }
+        ControlCode::CarriageReturn
         | ControlCode::DataLinkEscape
         | ControlCode::DeviceControlOne
         | ControlCode::DeviceControlTwo
@@ -40,22 +59,7 @@ pub fn process_control(surface: &mut Surface, control_code: ControlCode) -> Sequ
                 y: Position::Relative(0),
             })
         }
-        ControlCode::RI => {
-            let (x, y) = surface.cursor_position();
-            if y == 0 {
-                surface.add_change(Change::CursorPosition {
-                    x: Position::Absolute(x),
-                    y: Position::Absolute(0),
-                })
-            } else {
-                surface.add_change(Change::CursorPosition {
-                    x: Position::Absolute(x),
-                    y: Position::Relative(-1),
-                })
-            }
-        }
         ControlCode::Null
-        | ControlCode::NEL
         | ControlCode::StartOfHeading
         | ControlCode::StartOfText
         | ControlCode::EndOfText
@@ -65,10 +69,8 @@ pub fn process_control(surface: &mut Surface, control_code: ControlCode) -> Sequ
         | ControlCode::Bell
         | ControlCode::BPH
         | ControlCode::NBH
-        | ControlCode::IND
         | ControlCode::SSA
         | ControlCode::ESA
-        | ControlCode::HTS
         | ControlCode::HTJ
         | ControlCode::VTS
         | ControlCode::PLD
@@ -104,7 +106,7 @@ mod tests {
     }
 
     fn apply_control(surface: &mut Surface, code: ControlCode) -> SequenceNo {
-        process_control(surface, code)
+        process_control(surface, &mut TerminalState::default(), code)
     }
 
     #[test]
@@ -159,4 +161,66 @@ mod tests {
         let (_, after_y) = s.cursor_position();
         assert_eq!(after_y, before_y - 1);
     }
+
+    #[test]
+    fn test_linefeed_at_bottom_scrolls() {
+        let mut s = make_surface();
+        s.add_change("A");
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(2),
+        });
+        s.add_change("C");
+        apply_control(&mut s, ControlCode::LineFeed);
+
+        let (_, y) = s.cursor_position();
+        assert_eq!(y, 2);
+        assert!(s.screen_chars_to_string().starts_with("          \nC"));
+    }
+
+    #[test]
+    fn test_ri_at_top_scrolls_down() {
+        let mut s = make_surface();
+        s.add_change("A");
+        apply_control(&mut s, ControlCode::RI);
+
+        assert_eq!(s.cursor_position(), (1, 0));
+        assert!(s.screen_chars_to_string().starts_with("          \nA"));
+    }
+
+    #[test]
+    fn test_ind_moves_down() {
+        let mut s = make_surface();
+        apply_control(&mut s, ControlCode::IND);
+        assert_eq!(s.cursor_position(), (0, 1));
+    }
+
+    #[test]
+    fn test_nel_moves_to_next_line_start() {
+        let mut s = make_surface();
+        s.add_change("ABC");
+        apply_control(&mut s, ControlCode::NEL);
+        assert_eq!(s.cursor_position(), (0, 1));
+    }
+
+    #[test]
+    fn test_horizontal_tab() {
+        let mut s = make_surface();
+        s.add_change("AB");
+        apply_control(&mut s, ControlCode::HorizontalTab);
+        assert_eq!(s.cursor_position(), (8, 0));
+        apply_control(&mut s, ControlCode::HorizontalTab);
+        assert_eq!(s.cursor_position(), (9, 0));
+    }
+
+    #[test]
+    fn test_hts_sets_tab_stop() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        s.add_change("AB");
+        process_control(&mut s, &mut state, ControlCode::HTS);
+        process_control(&mut s, &mut state, ControlCode::CarriageReturn);
+        process_control(&mut s, &mut state, ControlCode::HorizontalTab);
+        assert_eq!(s.cursor_position(), (2, 0));
+    }
 }
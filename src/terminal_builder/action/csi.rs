@@ -1,57 +1,131 @@
 use std::io::{self};
 
 use termwiz::{
-    escape::{CSI, csi::Edit},
+    escape::{
+        CSI,
+        csi::{Edit, EraseInDisplay, EraseInLine},
+    },
     surface::{Change, Position, SEQ_ZERO, SequenceNo, Surface},
 };
 
 mod cursor;
+mod device;
+mod mode;
 mod sgr;
+mod window;
 
-use crate::terminal_builder::action::csi::{cursor::process_cursor, sgr::process_sgr};
+use crate::terminal_builder::{
+    action::{
+        csi::{
+            cursor::process_cursor, device::process_device, mode::process_mode, sgr::process_sgr,
+            window::process_window,
+        },
+        print::process_print_string,
+    },
+    state::TerminalState,
+    utils::{delete_cells, fill_cells, insert_cells},
+};
 
-pub fn process_csi(surface: &mut Surface, writer: &mut dyn io::Write, csi: &CSI) -> SequenceNo {
+pub fn process_csi(
+    surface: &mut Surface,
+    state: &mut TerminalState,
+    writer: &mut dyn io::Write,
+    csi: &CSI,
+) -> SequenceNo {
     match csi {
-        CSI::Sgr(sgr) => process_sgr(surface, sgr),
-        CSI::Cursor(cursor) => process_cursor(surface, writer, cursor),
-        CSI::Edit(edit) => process_edit(surface, edit),
-        CSI::Mode(_)
-        | CSI::Device(_)
-        | CSI::Mouse(_)
-        | CSI::Window(_)
-        | CSI::Keyboard(_)
-        | CSI::SelectCharacterPath(_, _)
-        | CSI::Unspecified(_) => SEQ_ZERO,
+        CSI::Sgr(sgr) => process_sgr(surface, state, sgr),
+        CSI::Cursor(cursor) => process_cursor(surface, state, writer, cursor),
+        CSI::Edit(edit) => process_edit(surface, state, edit),
+        CSI::Mode(mode) => process_mode(surface, state, mode),
+        CSI::Device(device) => process_device(writer, device),
+        CSI::Window(window) => process_window(surface, writer, window),
+        CSI::Mouse(_) | CSI::Keyboard(_) | CSI::SelectCharacterPath(_, _) | CSI::Unspecified(_) => {
+            SEQ_ZERO
+        }
     }
 }
 
-fn process_edit(surface: &mut Surface, edit: &Edit) -> SequenceNo {
+fn process_edit(surface: &mut Surface, state: &mut TerminalState, edit: &Edit) -> SequenceNo {
+    let (x, y) = surface.cursor_position();
+    let (width, height) = surface.dimensions();
+    let blank = state.blank_cell();
+
     match edit {
-        Edit::EraseCharacter(n) => {
-            let (x, y) = surface.cursor_position();
-            surface.add_change(Change::Text(" ".repeat(*n as usize)));
-            surface.add_change(Change::CursorPosition {
-                x: Position::Absolute(x),
-                y: Position::Absolute(y),
-            })
+        Edit::EraseCharacter(n) => fill_cells(surface, y, x..x + *n as usize, &blank),
+        Edit::EraseInLine(erase) => {
+            let cols = match erase {
+                EraseInLine::EraseToEndOfLine => x..width,
+                EraseInLine::EraseToStartOfLine => 0..x + 1,
+                EraseInLine::EraseLine => 0..width,
+            };
+            fill_cells(surface, y, cols, &blank);
+        }
+        Edit::EraseInDisplay(erase) => {
+            let (rows, partial) = match erase {
+                EraseInDisplay::EraseToEndOfDisplay => (y + 1..height, Some(x..width)),
+                EraseInDisplay::EraseToStartOfDisplay => (0..y, Some(0..x + 1)),
+                EraseInDisplay::EraseDisplay => (0..height, None),
+                EraseInDisplay::EraseScrollback => {
+                    state.clear_scrollback();
+                    (0..0, None)
+                }
+            };
+            for row in rows {
+                fill_cells(surface, row, 0..width, &blank);
+            }
+            if let Some(cols) = partial {
+                fill_cells(surface, y, cols, &blank);
+            }
+        }
+        Edit::InsertLine(n) => {
+            let region = state.scroll_region(surface);
+            if region.contains(&y) {
+                state.scroll_down(surface, y..region.end, *n as usize);
+                return carriage_return(surface);
+            }
+        }
+        Edit::DeleteLine(n) => {
+            let region = state.scroll_region(surface);
+            if region.contains(&y) {
+                state.scroll_up(surface, y..region.end, *n as usize);
+                return carriage_return(surface);
+            }
+        }
+        Edit::ScrollUp(n) => {
+            return state.scroll_up(surface, state.scroll_region(surface), *n as usize);
+        }
+        Edit::ScrollDown(n) => {
+            return state.scroll_down(surface, state.scroll_region(surface), *n as usize);
+        }
+        Edit::InsertCharacter(n) => insert_cells(surface, y, x, *n as usize, &blank),
+        Edit::DeleteCharacter(n) => delete_cells(surface, y, x, *n as usize, &blank),
+        Edit::Repeat(n) => {
+            if let Some(text) = state.last_printed() {
+                // More repeats than cells left in the scroll region only scroll
+                let bottom = state.scroll_region(surface).end.saturating_sub(1);
+                let cells_left = width.saturating_sub(x) + width * bottom.saturating_sub(y);
+                let count = (*n as usize).min(cells_left);
+                return process_print_string(surface, state, &text.repeat(count));
+            }
         }
-        Edit::EraseInLine(_)
-        | Edit::InsertCharacter(_)
-        | Edit::InsertLine(_)
-        | Edit::ScrollDown(_)
-        | Edit::ScrollUp(_)
-        | Edit::EraseInDisplay(_)
-        | Edit::Repeat(_)
-        | Edit::DeleteCharacter(_)
-        | Edit::DeleteLine(_) => SEQ_ZERO,
     }
+
+    SEQ_ZERO
+}
+
+fn carriage_return(surface: &mut Surface) -> SequenceNo {
+    surface.add_change(Change::CursorPosition {
+        x: Position::Absolute(0),
+        y: Position::Relative(0),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use termwiz::{
-        cell::Intensity,
+        cell::{Intensity, Underline},
+        color::{ColorAttribute, ColorSpec},
         escape::{
             CSI,
             csi::{Cursor as CsiCursor, Sgr},
@@ -63,11 +137,27 @@ mod tests {
         Surface::new(10, 3)
     }
 
+    fn apply_csi(surface: &mut Surface, csi: &CSI) -> SequenceNo {
+        process_csi(
+            surface,
+            &mut TerminalState::default(),
+            &mut std::io::sink(),
+            csi,
+        )
+    }
+
+    fn line(surface: &Surface, row: usize) -> String {
+        surface.screen_lines()[row]
+            .visible_cells()
+            .map(|c| c.str().to_string())
+            .collect()
+    }
+
     #[test]
     fn csi_sgr_applies_intensity() {
         let mut s = make_surface();
         let csi = CSI::Sgr(Sgr::Intensity(Intensity::Bold));
-        process_csi(&mut s, &mut std::io::sink(), &csi);
+        apply_csi(&mut s, &csi);
         s.add_change("A");
         let screen = s.screen_cells();
         let cell = &screen[0][0];
@@ -79,7 +169,7 @@ mod tests {
         let mut s = make_surface();
         s.add_change("X");
         let csi = CSI::Cursor(CsiCursor::Right(3));
-        process_csi(&mut s, &mut std::io::sink(), &csi);
+        apply_csi(&mut s, &csi);
         let (x, y) = s.cursor_position();
         assert_eq!(x, 4);
         assert_eq!(y, 0);
@@ -90,9 +180,245 @@ mod tests {
         let mut s = make_surface();
         s.add_change("ABCDE");
         let csi = CSI::Edit(Edit::EraseCharacter(3));
-        process_csi(&mut s, &mut std::io::sink(), &csi);
+        apply_csi(&mut s, &csi);
         let content = s.screen_chars_to_string();
         println!("{content:?}");
         assert!(content.starts_with("ABCDE"));
     }
+
+    #[test]
+    fn csi_edit_erase_character_keeps_cursor() {
+        let mut s = make_surface();
+        s.add_change("ABCDE");
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(1),
+            y: Position::Absolute(0),
+        });
+        apply_csi(&mut s, &CSI::Edit(Edit::EraseCharacter(2)));
+        assert_eq!(line(&s, 0), "A  DE     ");
+        assert_eq!(s.cursor_position(), (1, 0));
+    }
+
+    #[test]
+    fn csi_erase_in_line_modes() {
+        for (mode, expected) in [
+            (EraseInLine::EraseToEndOfLine, "ABC       "),
+            (EraseInLine::EraseToStartOfLine, "    EFGHIJ"),
+            (EraseInLine::EraseLine, "          "),
+        ] {
+            let mut s = make_surface();
+            s.add_change("ABCDEFGHIJ");
+            s.add_change(Change::CursorPosition {
+                x: Position::Absolute(3),
+                y: Position::Absolute(0),
+            });
+            apply_csi(&mut s, &CSI::Edit(Edit::EraseInLine(mode)));
+            assert_eq!(line(&s, 0), expected, "{mode:?}");
+            assert_eq!(s.cursor_position(), (3, 0));
+        }
+    }
+
+    #[test]
+    fn csi_erase_in_display_modes() {
+        for (mode, expected) in [
+            (
+                EraseInDisplay::EraseToEndOfDisplay,
+                ["AAAAAAAAAA", "B         ", "          "],
+            ),
+            (
+                EraseInDisplay::EraseToStartOfDisplay,
+                ["          ", "  BBBBBBBB", "CCCCCCCCCC"],
+            ),
+            (
+                EraseInDisplay::EraseDisplay,
+                ["          ", "          ", "          "],
+            ),
+        ] {
+            let mut s = make_surface();
+            s.add_change("AAAAAAAAAABBBBBBBBBBCCCCCCCCCC");
+            s.add_change(Change::CursorPosition {
+                x: Position::Absolute(1),
+                y: Position::Absolute(1),
+            });
+            apply_csi(&mut s, &CSI::Edit(Edit::EraseInDisplay(mode)));
+            for (row, text) in expected.iter().enumerate() {
+                assert_eq!(line(&s, row), *text, "{mode:?} row {row}");
+            }
+            assert_eq!(s.cursor_position(), (1, 1));
+        }
+    }
+
+    #[test]
+    fn csi_erase_uses_current_background() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        let mut sink = std::io::sink();
+
+        process_csi(
+            &mut s,
+            &mut state,
+            &mut sink,
+            &CSI::Sgr(Sgr::Background(ColorSpec::PaletteIndex(4))),
+        );
+        process_csi(
+            &mut s,
+            &mut state,
+            &mut sink,
+            &CSI::Sgr(Sgr::Underline(Underline::Single)),
+        );
+        process_csi(
+            &mut s,
+            &mut state,
+            &mut sink,
+            &CSI::Edit(Edit::EraseInLine(EraseInLine::EraseLine)),
+        );
+
+        let screen = s.screen_cells();
+        let cell = &screen[0][5];
+        assert_eq!(cell.attrs().background(), ColorAttribute::PaletteIndex(4));
+        assert_eq!(cell.attrs().underline(), Underline::None);
+        assert_eq!(screen[1][5].attrs().background(), ColorAttribute::Default);
+    }
+
+    fn make_lines_surface() -> Surface {
+        let mut s = Surface::new(10, 5);
+        s.add_change("0\r\n1\r\n2\r\n3\r\n4");
+        s
+    }
+
+    fn first_column(surface: &Surface) -> String {
+        surface
+            .screen_lines()
+            .iter()
+            .map(|l| {
+                l.visible_cells()
+                    .next()
+                    .map_or(String::new(), |c| c.str().to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn csi_insert_and_delete_line_within_region() {
+        let mut s = make_lines_surface();
+        let mut state = TerminalState::default();
+        let mut sink = std::io::sink();
+        state.set_scroll_region(&s, 1, 4);
+
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(3),
+            y: Position::Absolute(2),
+        });
+        process_csi(
+            &mut s,
+            &mut state,
+            &mut sink,
+            &CSI::Edit(Edit::InsertLine(1)),
+        );
+        assert_eq!(first_column(&s), "01 24");
+        assert_eq!(s.cursor_position(), (0, 2));
+
+        process_csi(
+            &mut s,
+            &mut state,
+            &mut sink,
+            &CSI::Edit(Edit::DeleteLine(2)),
+        );
+        assert_eq!(first_column(&s), "01  4");
+    }
+
+    #[test]
+    fn csi_insert_line_outside_region_is_ignored() {
+        let mut s = make_lines_surface();
+        let mut state = TerminalState::default();
+        state.set_scroll_region(&s, 1, 3);
+
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(3),
+            y: Position::Absolute(4),
+        });
+        process_csi(
+            &mut s,
+            &mut state,
+            &mut std::io::sink(),
+            &CSI::Edit(Edit::InsertLine(1)),
+        );
+        assert_eq!(first_column(&s), "01234");
+        assert_eq!(s.cursor_position(), (3, 4));
+    }
+
+    #[test]
+    fn csi_scroll_up_and_down_whole_screen() {
+        let mut s = make_lines_surface();
+
+        apply_csi(&mut s, &CSI::Edit(Edit::ScrollUp(2)));
+        assert_eq!(first_column(&s), "234  ");
+
+        apply_csi(&mut s, &CSI::Edit(Edit::ScrollDown(1)));
+        assert_eq!(first_column(&s), " 234 ");
+    }
+
+    #[test]
+    fn csi_insert_character_shifts_right() {
+        let mut s = Surface::new(6, 1);
+        s.add_change("abcdef");
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(1),
+            y: Position::Absolute(0),
+        });
+
+        apply_csi(&mut s, &CSI::Edit(Edit::InsertCharacter(2)));
+
+        assert_eq!(line(&s, 0), "a  bcd");
+        assert_eq!(s.cursor_position(), (1, 0));
+    }
+
+    #[test]
+    fn csi_delete_character_shifts_left() {
+        let mut s = Surface::new(6, 1);
+        s.add_change("abcdef");
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(1),
+            y: Position::Absolute(0),
+        });
+
+        apply_csi(&mut s, &CSI::Edit(Edit::DeleteCharacter(2)));
+        assert_eq!(line(&s, 0), "adef  ");
+
+        apply_csi(&mut s, &CSI::Edit(Edit::DeleteCharacter(99)));
+        assert_eq!(line(&s, 0), "a     ");
+    }
+
+    #[test]
+    fn csi_repeat_prints_last_character() {
+        let mut s = Surface::new(6, 1);
+        let mut state = TerminalState::default();
+        process_print_string(&mut s, &mut state, "ab");
+
+        process_csi(
+            &mut s,
+            &mut state,
+            &mut std::io::sink(),
+            &CSI::Edit(Edit::Repeat(3)),
+        );
+
+        assert_eq!(line(&s, 0), "abbbb ");
+    }
+
+    #[test]
+    fn csi_repeat_huge_count_fills_screen() {
+        let mut s = Surface::new(4, 2);
+        let mut state = TerminalState::default();
+        process_print_string(&mut s, &mut state, "a");
+
+        process_csi(
+            &mut s,
+            &mut state,
+            &mut std::io::sink(),
+            &CSI::Edit(Edit::Repeat(2_000_000_000)),
+        );
+
+        assert_eq!(line(&s, 0), "aaaa");
+        assert_eq!(line(&s, 1), "aaaa");
+    }
 }
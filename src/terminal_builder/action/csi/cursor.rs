@@ -1,45 +1,35 @@
-use std::{
-    io::{self},
-    sync::Mutex,
-};
+use std::io::{self};
 
 use termwiz::{
-    escape::{CSI, OneBased, csi::Cursor},
-    surface::{Change, Position, SEQ_ZERO, SequenceNo, Surface},
+    escape::{
+        CSI, OneBased,
+        csi::{Cursor, CursorStyle, CursorTabulationControl, TabulationClear},
+    },
+    surface::{Change, CursorShape, Position, SEQ_ZERO, SequenceNo, Surface},
 };
-use tracing::warn;
-
-use crate::terminal_builder::utils::{tabulate, tabulate_back};
 
-static SAVED_POSITIONS: std::sync::LazyLock<Mutex<Vec<(usize, usize)>>> =
-    std::sync::LazyLock::new(|| Mutex::new(Vec::new()));
+use crate::terminal_builder::state::TerminalState;
 
 pub fn process_cursor(
     surface: &mut Surface,
+    state: &mut TerminalState,
     writer: &mut dyn io::Write,
     cursor: &Cursor,
 ) -> SequenceNo {
     match cursor {
-        Cursor::BackwardTabulation(n) => surface.add_change(Change::CursorPosition {
-            x: Position::Absolute(tabulate_back(surface.cursor_position().0, *n as usize)),
-            y: Position::Relative(0),
-        }),
-        Cursor::ForwardTabulation(n) => surface.add_change(Change::CursorPosition {
-            x: Position::Absolute(tabulate(surface.cursor_position().0, *n as usize)),
-            y: Position::Relative(0),
-        }),
+        Cursor::BackwardTabulation(n) => state.backward_tab(surface, *n as usize),
+        Cursor::ForwardTabulation(n) => state.horizontal_tab(surface, *n as usize),
         Cursor::CharacterAbsolute(n) | Cursor::CharacterPositionAbsolute(n) => {
             surface.add_change(Change::CursorPosition {
                 x: Position::Absolute(n.as_zero_based() as usize),
                 y: Position::Relative(0),
             })
         }
-        Cursor::CharacterAndLinePosition { line, col } => {
-            surface.add_change(Change::CursorPosition {
+        Cursor::CharacterAndLinePosition { line, col } | Cursor::Position { line, col } => surface
+            .add_change(Change::CursorPosition {
                 x: Position::Absolute(col.as_zero_based() as usize),
-                y: Position::Absolute(line.as_zero_based() as usize),
-            })
-        }
+                y: Position::Absolute(state.origin_row(surface, line.as_zero_based() as usize)),
+            }),
         Cursor::CharacterPositionForward(n) | Cursor::Right(n) => {
             surface.add_change(Change::CursorPosition {
                 x: Position::Relative(*n as isize),
@@ -55,81 +45,115 @@ pub fn process_cursor(
         Cursor::LinePositionForward(n) | Cursor::Down(n) => {
             surface.add_change(Change::CursorPosition {
                 x: Position::Relative(0),
-                y: Position::Relative(*n as isize),
+                y: Position::Absolute(state.vertical_move_row(surface, *n as isize)),
             })
         }
         Cursor::LinePositionBackward(n) | Cursor::Up(n) => {
             surface.add_change(Change::CursorPosition {
                 x: Position::Relative(0),
-                y: Position::Relative(-(*n as isize)),
+                y: Position::Absolute(state.vertical_move_row(surface, -(*n as isize))),
             })
         }
         Cursor::LinePositionAbsolute(n) => surface.add_change(Change::CursorPosition {
-            x: Position::Absolute(0),
-            y: Position::Absolute(*n as usize),
+            x: Position::Relative(0),
+            y: Position::Absolute(state.origin_row(surface, (*n as usize).saturating_sub(1))),
         }),
         Cursor::NextLine(n) => surface.add_change(Change::CursorPosition {
             x: Position::Absolute(0),
-            y: Position::Relative(*n as isize),
+            y: Position::Absolute(state.vertical_move_row(surface, *n as isize)),
         }),
         Cursor::PrecedingLine(n) => surface.add_change(Change::CursorPosition {
             x: Position::Absolute(0),
-            y: Position::Relative(-(*n as isize)),
-        }),
-        Cursor::Position { line, col } => surface.add_change(Change::CursorPosition {
-            x: Position::Absolute(col.as_zero_based() as usize),
-            y: Position::Absolute(line.as_zero_based() as usize),
+            y: Position::Absolute(state.vertical_move_row(surface, -(*n as isize))),
         }),
         Cursor::SaveCursor => {
-            match SAVED_POSITIONS.lock() {
-                Ok(mut positions) => positions.push(surface.cursor_position()),
-                Err(poisoned) => {
-                    warn!("Mutex poisoned when saving cursor position, using inner value");
-                    poisoned.into_inner().push(surface.cursor_position());
-                }
-            }
-            SEQ_ZERO
-        }
-        Cursor::RestoreCursor => {
-            match SAVED_POSITIONS.lock() {
-                Ok(mut positions) => {
-                    if let Some((x, y)) = positions.pop() {
-                        return surface.add_change(Change::CursorPosition {
-                            x: Position::Absolute(x),
-                            y: Position::Absolute(y),
-                        });
-                    }
-                }
-                Err(poisoned) => {
-                    warn!("Mutex poisoned when restoring cursor position, using inner value");
-                    let value = poisoned.into_inner().pop();
-                    if let Some((x, y)) = value {
-                        return surface.add_change(Change::CursorPosition {
-                            x: Position::Absolute(x),
-                            y: Position::Absolute(y),
-                        });
-                    }
-                }
-            }
+            state.save_cursor(surface);
             SEQ_ZERO
         }
+        Cursor::RestoreCursor => state.restore_cursor(surface),
         Cursor::RequestActivePositionReport => {
-            let cursor_position = surface.cursor_position();
-            let col = OneBased::from_zero_based(cursor_position.0 as u32);
-            let line = OneBased::from_zero_based(cursor_position.1 as u32);
+            let (x, mut y) = surface.cursor_position();
+            if state.modes().origin {
+                y = y.saturating_sub(state.scroll_region(surface).start);
+            }
+            let col = OneBased::from_zero_based(x as u32);
+            let line = OneBased::from_zero_based(y as u32);
 
             let report = CSI::Cursor(Cursor::ActivePositionReport { line, col });
             write!(writer, "{report}").ok();
             writer.flush().ok();
             SEQ_ZERO
         }
-        Cursor::TabulationClear(_)
-        | Cursor::ActivePositionReport { .. }
-        | Cursor::TabulationControl(_)
+        Cursor::SetTopAndBottomMargins { top, bottom } => {
+            let top = top.as_zero_based() as usize;
+            let bottom = bottom.as_one_based() as usize;
+            if state.set_scroll_region(surface, top, bottom) {
+                state.home_cursor(surface)
+            } else {
+                SEQ_ZERO
+            }
+        }
+        Cursor::TabulationClear(clear) => {
+            process_tabulation_clear(surface, state, *clear);
+            SEQ_ZERO
+        }
+        Cursor::TabulationControl(control) => {
+            process_tabulation_control(surface, state, *control);
+            SEQ_ZERO
+        }
+        Cursor::CursorStyle(style) => {
+            state.set_cursor_shape(cursor_shape(*style));
+            SEQ_ZERO
+        }
+        Cursor::ActivePositionReport { .. }
         | Cursor::LineTabulation(_)
-        | Cursor::SetTopAndBottomMargins { .. }
-        | Cursor::SetLeftAndRightMargins { .. }
-        | Cursor::CursorStyle(_) => SEQ_ZERO,
+        | Cursor::SetLeftAndRightMargins { .. } => SEQ_ZERO,
+    }
+}
+
+/// Clears character tab stops (TBC). Stops are the same on every line, and
+/// line tab stops are not supported.
+fn process_tabulation_clear(surface: &Surface, state: &mut TerminalState, clear: TabulationClear) {
+    match clear {
+        TabulationClear::ClearCharacterTabStopAtActivePosition => state.clear_tab_stop(surface),
+        TabulationClear::ClearCharacterTabStopsAtActiveLine
+        | TabulationClear::ClearAllCharacterTabStops
+        | TabulationClear::ClearAllTabStops => state.clear_tab_stops(),
+        TabulationClear::ClearLineTabStopAtActiveLine | TabulationClear::ClearAllLineTabStops => (),
+    }
+}
+
+/// Sets or clears character tab stops (CTC).
+fn process_tabulation_control(
+    surface: &Surface,
+    state: &mut TerminalState,
+    control: CursorTabulationControl,
+) {
+    match control {
+        CursorTabulationControl::SetCharacterTabStopAtActivePosition => {
+            state.set_tab_stop(surface);
+        }
+        CursorTabulationControl::ClearCharacterTabStopAtActivePosition => {
+            state.clear_tab_stop(surface);
+        }
+        CursorTabulationControl::ClearAllCharacterTabStopsAtActiveLine
+        | CursorTabulationControl::ClearAllCharacterTabStops => state.clear_tab_stops(),
+        CursorTabulationControl::SetLineTabStopAtActiveLine
+        | CursorTabulationControl::ClearLineTabstopAtActiveLine
+        | CursorTabulationControl::ClearAllLineTabStops => (),
+    }
+}
+
+/// Cursor shape selected by DECSCUSR.
+fn cursor_shape(style: CursorStyle) -> CursorShape {
+    match style {
+        CursorStyle::Default => CursorShape::Default,
+        CursorStyle::BlinkingBlock => CursorShape::BlinkingBlock,
+        CursorStyle::SteadyBlock => CursorShape::SteadyBlock,
+        CursorStyle::BlinkingUnderline => CursorShape::BlinkingUnderline,
+        CursorStyle::SteadyUnderline => CursorShape::SteadyUnderline,
+        CursorStyle::BlinkingBar => CursorShape::BlinkingBar,
+        CursorStyle::SteadyBar => CursorShape::SteadyBar,
     }
 }
 
@@ -145,8 +169,16 @@ mod tests {
     }
 
     fn apply_cursor(surface: &mut Surface, cursor: &CsiCursor) -> SequenceNo {
+        apply_cursor_with_state(surface, &mut TerminalState::default(), cursor)
+    }
+
+    fn apply_cursor_with_state(
+        surface: &mut Surface,
+        state: &mut TerminalState,
+        cursor: &CsiCursor,
+    ) -> SequenceNo {
         let mut writer = std::io::sink();
-        process_cursor(surface, &mut writer, cursor)
+        process_cursor(surface, state, &mut writer, cursor)
     }
 
     #[test]
@@ -283,23 +315,24 @@ mod tests {
     #[test]
     fn save_and_restore_cursor_position_stack_behaviour() {
         let mut s = make_surface();
+        let mut state = TerminalState::default();
 
         s.add_change(Change::CursorPosition {
             x: Position::Absolute(3),
             y: Position::Absolute(4),
         });
 
-        apply_cursor(&mut s, &CsiCursor::SaveCursor);
-        apply_cursor(&mut s, &CsiCursor::Right(4));
-        apply_cursor(&mut s, &CsiCursor::SaveCursor);
-        apply_cursor(&mut s, &CsiCursor::Down(2));
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::SaveCursor);
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::Right(4));
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::SaveCursor);
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::Down(2));
 
-        apply_cursor(&mut s, &CsiCursor::RestoreCursor);
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::RestoreCursor);
         let (x1, y1) = s.cursor_position();
         assert!(x1 > 0);
         assert!(y1 > 0);
 
-        apply_cursor(&mut s, &CsiCursor::RestoreCursor);
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::RestoreCursor);
         let (x2, y2) = s.cursor_position();
         assert!(x2 > 0);
         assert!(y2 > 0);
@@ -316,7 +349,7 @@ mod tests {
 
         let mut buf = Vec::new();
         let csr = CsiCursor::RequestActivePositionReport;
-        let seq = process_cursor(&mut s, &mut buf, &csr);
+        let seq = process_cursor(&mut s, &mut TerminalState::default(), &mut buf, &csr);
 
         assert!(!buf.is_empty());
         let _ = seq;
@@ -332,11 +365,65 @@ mod tests {
 
         apply_cursor(&mut s, &CsiCursor::ForwardTabulation(1));
         let (x_after, _) = s.cursor_position();
-        assert!(x_after > 0);
+        assert_eq!(x_after, 8);
 
         apply_cursor(&mut s, &CsiCursor::BackwardTabulation(1));
         let (x_after2, _) = s.cursor_position();
-        assert!(x_after2 > 0);
+        assert_eq!(x_after2, 0);
+    }
+
+    #[test]
+    fn tabulation_with_huge_count_stops_at_margins() {
+        let mut s = make_surface();
+        let width = s.dimensions().0;
+
+        apply_cursor(&mut s, &CsiCursor::ForwardTabulation(u32::MAX));
+        assert_eq!(s.cursor_position().0, width - 1);
+
+        apply_cursor(&mut s, &CsiCursor::BackwardTabulation(u32::MAX));
+        assert_eq!(s.cursor_position().0, 0);
+    }
+
+    #[test]
+    fn tabulation_uses_set_and_cleared_stops() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(3),
+            y: Position::Absolute(0),
+        });
+        apply_cursor_with_state(
+            &mut s,
+            &mut state,
+            &CsiCursor::TabulationControl(
+                CursorTabulationControl::SetCharacterTabStopAtActivePosition,
+            ),
+        );
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(16),
+            y: Position::Absolute(0),
+        });
+        apply_cursor_with_state(
+            &mut s,
+            &mut state,
+            &CsiCursor::TabulationClear(TabulationClear::ClearCharacterTabStopAtActivePosition),
+        );
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::BackwardTabulation(2));
+        assert_eq!(s.cursor_position().0, 3);
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::ForwardTabulation(2));
+        assert_eq!(s.cursor_position().0, 24);
+
+        apply_cursor_with_state(
+            &mut s,
+            &mut state,
+            &CsiCursor::TabulationClear(TabulationClear::ClearAllCharacterTabStops),
+        );
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::BackwardTabulation(1));
+        assert_eq!(s.cursor_position().0, 0);
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::ForwardTabulation(1));
+        assert_eq!(s.cursor_position().0, 79);
     }
 
     #[test]
@@ -373,7 +460,7 @@ mod tests {
         assert_eq!(s.cursor_position().0, 10);
 
         apply_cursor(&mut s, &CsiCursor::LinePositionAbsolute(5));
-        assert_eq!(s.cursor_position().1, 5);
+        assert_eq!(s.cursor_position(), (10, 4));
 
         apply_cursor(
             &mut s,
@@ -436,21 +523,22 @@ mod tests {
     #[test]
     fn test_save_and_restore_cursor_stack() {
         let mut s = make_surface();
+        let mut state = TerminalState::default();
         s.add_change(Change::CursorPosition {
             x: Position::Absolute(2),
             y: Position::Absolute(3),
         });
 
-        apply_cursor(&mut s, &CsiCursor::SaveCursor);
-        apply_cursor(&mut s, &CsiCursor::Right(4));
-        apply_cursor(&mut s, &CsiCursor::SaveCursor);
-        apply_cursor(&mut s, &CsiCursor::Down(2));
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::SaveCursor);
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::Right(4));
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::SaveCursor);
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::Down(2));
 
-        apply_cursor(&mut s, &CsiCursor::RestoreCursor);
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::RestoreCursor);
         let (x1, y1) = s.cursor_position();
         assert_eq!((x1, y1), (6, 3));
 
-        apply_cursor(&mut s, &CsiCursor::RestoreCursor);
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::RestoreCursor);
         let (x2, y2) = s.cursor_position();
         assert_eq!((x2, y2), (2, 3));
     }
@@ -464,7 +552,12 @@ mod tests {
         });
 
         let mut buf = Vec::new();
-        let seq = process_cursor(&mut s, &mut buf, &CsiCursor::RequestActivePositionReport);
+        let seq = process_cursor(
+            &mut s,
+            &mut TerminalState::default(),
+            &mut buf,
+            &CsiCursor::RequestActivePositionReport,
+        );
         assert!(!buf.is_empty());
         let _ = seq;
     }
@@ -518,4 +611,229 @@ mod tests {
         assert_eq!(x2, 3);
         assert_eq!(y2, 0);
     }
+
+    #[test]
+    fn set_top_and_bottom_margins_homes_cursor() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(5),
+            y: Position::Absolute(5),
+        });
+
+        process_cursor(
+            &mut s,
+            &mut state,
+            &mut std::io::sink(),
+            &CsiCursor::SetTopAndBottomMargins {
+                top: OneBased::new(3),
+                bottom: OneBased::new(10),
+            },
+        );
+
+        assert_eq!(s.cursor_position(), (0, 0));
+        assert_eq!(state.scroll_region(&s), 2..10);
+    }
+
+    #[test]
+    fn set_top_and_bottom_margins_defaults_to_full_screen() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        state.set_scroll_region(&s, 2, 10);
+
+        process_cursor(
+            &mut s,
+            &mut state,
+            &mut std::io::sink(),
+            &CsiCursor::SetTopAndBottomMargins {
+                top: OneBased::new(1),
+                bottom: OneBased::new(u32::MAX),
+            },
+        );
+
+        assert_eq!(state.scroll_region(&s), 0..24);
+    }
+
+    #[test]
+    fn test_saved_cursor_is_per_state() {
+        let mut first = make_surface();
+        let mut first_state = TerminalState::default();
+        first.add_change(Change::CursorPosition {
+            x: Position::Absolute(5),
+            y: Position::Absolute(5),
+        });
+        apply_cursor_with_state(&mut first, &mut first_state, &CsiCursor::SaveCursor);
+
+        let mut second = make_surface();
+        let mut second_state = TerminalState::default();
+        second.add_change(Change::CursorPosition {
+            x: Position::Absolute(2),
+            y: Position::Absolute(2),
+        });
+        apply_cursor_with_state(&mut second, &mut second_state, &CsiCursor::RestoreCursor);
+
+        assert_eq!(second.cursor_position(), (0, 0));
+    }
+
+    #[test]
+    fn test_restore_cursor_twice_after_single_save() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(3),
+            y: Position::Absolute(1),
+        });
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::SaveCursor);
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::Down(2));
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::RestoreCursor);
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::Down(2));
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::RestoreCursor);
+
+        assert_eq!(s.cursor_position(), (3, 1));
+    }
+
+    #[test]
+    fn test_origin_mode_positions_relative_to_region() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        apply_cursor_with_state(
+            &mut s,
+            &mut state,
+            &CsiCursor::SetTopAndBottomMargins {
+                top: OneBased::new(5),
+                bottom: OneBased::new(10),
+            },
+        );
+        state.modes_mut().origin = true;
+
+        apply_cursor_with_state(
+            &mut s,
+            &mut state,
+            &CsiCursor::Position {
+                line: OneBased::new(2),
+                col: OneBased::new(3),
+            },
+        );
+        assert_eq!(s.cursor_position(), (2, 5));
+
+        apply_cursor_with_state(
+            &mut s,
+            &mut state,
+            &CsiCursor::Position {
+                line: OneBased::new(20),
+                col: OneBased::new(1),
+            },
+        );
+        assert_eq!(s.cursor_position(), (0, 9));
+
+        let mut writer = Vec::new();
+        process_cursor(
+            &mut s,
+            &mut state,
+            &mut writer,
+            &CsiCursor::RequestActivePositionReport,
+        );
+        assert_eq!(writer, b"\x1b[6;1R");
+    }
+
+    #[test]
+    fn test_line_position_absolute_in_origin_mode() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        state.set_scroll_region(&s, 4, 10);
+        state.modes_mut().origin = true;
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(7),
+            y: Position::Absolute(5),
+        });
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::LinePositionAbsolute(1));
+        assert_eq!(s.cursor_position(), (7, 4));
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::LinePositionAbsolute(3));
+        assert_eq!(s.cursor_position(), (7, 6));
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::LinePositionAbsolute(50));
+        assert_eq!(s.cursor_position(), (7, 9));
+    }
+
+    #[test]
+    fn test_vertical_moves_stop_at_scroll_region() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        state.set_scroll_region(&s, 4, 10);
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(3),
+            y: Position::Absolute(6),
+        });
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::Down(20));
+        assert_eq!(s.cursor_position(), (3, 9));
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::Up(20));
+        assert_eq!(s.cursor_position(), (3, 4));
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::LinePositionForward(20));
+        assert_eq!(s.cursor_position(), (3, 9));
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::LinePositionBackward(20));
+        assert_eq!(s.cursor_position(), (3, 4));
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::NextLine(20));
+        assert_eq!(s.cursor_position(), (0, 9));
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::PrecedingLine(20));
+        assert_eq!(s.cursor_position(), (0, 4));
+    }
+
+    #[test]
+    fn test_vertical_moves_outside_scroll_region_reach_screen_edges() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        state.set_scroll_region(&s, 4, 10);
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(3),
+            y: Position::Absolute(2),
+        });
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::Up(20));
+        assert_eq!(s.cursor_position(), (3, 0));
+
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(3),
+            y: Position::Absolute(12),
+        });
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::Down(50));
+        assert_eq!(s.cursor_position(), (3, 23));
+    }
+
+    #[test]
+    fn test_vertical_moves_stay_in_region_in_origin_mode() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        state.set_scroll_region(&s, 4, 10);
+        state.modes_mut().origin = true;
+        state.home_cursor(&mut s);
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::PrecedingLine(3));
+        assert_eq!(s.cursor_position(), (0, 4));
+
+        apply_cursor_with_state(&mut s, &mut state, &CsiCursor::NextLine(30));
+        assert_eq!(s.cursor_position(), (0, 9));
+    }
+
+    #[test]
+    fn cursor_style_sets_shape() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        assert_eq!(state.cursor_shape(), CursorShape::Default);
+
+        apply_cursor_with_state(
+            &mut s,
+            &mut state,
+            &CsiCursor::CursorStyle(CursorStyle::SteadyBar),
+        );
+        assert_eq!(state.cursor_shape(), CursorShape::SteadyBar);
+    }
 }
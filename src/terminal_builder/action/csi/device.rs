@@ -0,0 +1,102 @@
+use std::{fmt::Display, io};
+
+use termwiz::{
+    escape::{
+        CSI,
+        csi::{
+            Device, DeviceAttribute, DeviceAttributeCodes, DeviceAttributeFlags, DeviceAttributes,
+        },
+    },
+    surface::{SEQ_ZERO, SequenceNo},
+};
+
+/// Secondary device attributes: VT220, firmware version, no ROM cartridge
+const SECONDARY_DEVICE_ATTRIBUTES: &str = "\x1b[>1;10;0c";
+/// Tertiary device attributes: a zero unit id
+const TERTIARY_DEVICE_ATTRIBUTES: &str = "\x1bP!|00000000\x1b\\";
+/// Operating status: ready, no malfunction
+const STATUS_OK: &str = "\x1b[0n";
+
+/// Answers the device queries a program sends to identify the terminal,
+/// presenting it as a VT220 with sixel graphics and ANSI colors.
+pub fn process_device(writer: &mut dyn io::Write, device: &Device) -> SequenceNo {
+    match device {
+        Device::RequestPrimaryDeviceAttributes => {
+            let attributes = DeviceAttributeFlags::new(vec![
+                DeviceAttribute::Code(DeviceAttributeCodes::SixelGraphics),
+                DeviceAttribute::Code(DeviceAttributeCodes::AnsiColor),
+            ]);
+            reply(
+                writer,
+                CSI::Device(Box::new(Device::DeviceAttributes(DeviceAttributes::Vt220(
+                    attributes,
+                )))),
+            );
+        }
+        Device::RequestSecondaryDeviceAttributes => reply(writer, SECONDARY_DEVICE_ATTRIBUTES),
+        Device::RequestTertiaryDeviceAttributes => reply(writer, TERTIARY_DEVICE_ATTRIBUTES),
+        Device::RequestTerminalNameAndVersion => reply(
+            writer,
+            format!(
+                "\x1bP>|{} {}\x1b\\",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            ),
+        ),
+        Device::RequestTerminalParameters(_) => {
+            reply(writer, CSI::Device(Box::new(device.clone())));
+        }
+        Device::StatusReport => reply(writer, STATUS_OK),
+        Device::DeviceAttributes(_) | Device::SoftReset | Device::XtSmGraphics(_) => (),
+    }
+    SEQ_ZERO
+}
+
+fn reply(writer: &mut dyn io::Write, response: impl Display) {
+    write!(writer, "{response}").ok();
+    writer.flush().ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply_to(device: Device) -> String {
+        let mut writer = Vec::new();
+        process_device(&mut writer, &device);
+        String::from_utf8(writer).expect("reply should be UTF-8")
+    }
+
+    #[test]
+    fn test_primary_device_attributes() {
+        assert_eq!(
+            reply_to(Device::RequestPrimaryDeviceAttributes),
+            "\x1b[?62;4;22c"
+        );
+    }
+
+    #[test]
+    fn test_secondary_device_attributes() {
+        assert_eq!(
+            reply_to(Device::RequestSecondaryDeviceAttributes),
+            "\x1b[>1;10;0c"
+        );
+    }
+
+    #[test]
+    fn test_status_report() {
+        assert_eq!(reply_to(Device::StatusReport), "\x1b[0n");
+    }
+
+    #[test]
+    fn test_terminal_name_and_version() {
+        let reply = reply_to(Device::RequestTerminalNameAndVersion);
+        assert!(reply.starts_with("\x1bP>|shellshot "));
+        assert!(reply.ends_with("\x1b\\"));
+    }
+
+    #[test]
+    fn test_replies_are_not_answered() {
+        assert_eq!(reply_to(Device::SoftReset), "");
+    }
+}
@@ -0,0 +1,267 @@
+use termwiz::{
+    escape::csi::{DecPrivateMode, DecPrivateModeCode, Mode, TerminalMode, TerminalModeCode},
+    surface::{Change, CursorVisibility, SEQ_ZERO, SequenceNo, Surface},
+};
+
+use crate::terminal_builder::state::TerminalState;
+
+pub fn process_mode(surface: &mut Surface, state: &mut TerminalState, mode: &Mode) -> SequenceNo {
+    match mode {
+        Mode::SetDecPrivateMode(DecPrivateMode::Code(code)) => {
+            set_dec_private_mode(surface, state, code, true);
+        }
+        Mode::ResetDecPrivateMode(DecPrivateMode::Code(code)) => {
+            set_dec_private_mode(surface, state, code, false);
+        }
+        Mode::SetMode(TerminalMode::Code(TerminalModeCode::Insert)) => {
+            state.modes_mut().insert = true;
+        }
+        Mode::ResetMode(TerminalMode::Code(TerminalModeCode::Insert)) => {
+            state.modes_mut().insert = false;
+        }
+        Mode::SetDecPrivateMode(DecPrivateMode::Unspecified(_))
+        | Mode::ResetDecPrivateMode(DecPrivateMode::Unspecified(_))
+        | Mode::SaveDecPrivateMode(_)
+        | Mode::RestoreDecPrivateMode(_)
+        | Mode::QueryDecPrivateMode(_)
+        | Mode::SetMode(_)
+        | Mode::ResetMode(_)
+        | Mode::QueryMode(_)
+        | Mode::XtermKeyMode { .. } => (),
+    }
+
+    SEQ_ZERO
+}
+
+fn set_dec_private_mode(
+    surface: &mut Surface,
+    state: &mut TerminalState,
+    code: &DecPrivateModeCode,
+    enable: bool,
+) {
+    match (code, enable) {
+        (DecPrivateModeCode::AutoWrap, _) => state.modes_mut().autowrap = enable,
+        (DecPrivateModeCode::ApplicationCursorKeys, _) => {
+            state.modes_mut().application_cursor_keys = enable;
+        }
+        (DecPrivateModeCode::OriginMode, _) => {
+            state.modes_mut().origin = enable;
+            state.home_cursor(surface);
+        }
+        (DecPrivateModeCode::ShowCursor, _) => {
+            state.modes_mut().cursor_visible = enable;
+            surface.add_change(Change::CursorVisibility(if enable {
+                CursorVisibility::Visible
+            } else {
+                CursorVisibility::Hidden
+            }));
+        }
+        (DecPrivateModeCode::EnableAlternateScreen, true)
+        | (DecPrivateModeCode::OptEnableAlternateScreen, true) => {
+            state.enter_alternate_screen(surface, false);
+        }
+        (DecPrivateModeCode::ClearAndEnableAlternateScreen, true) => {
+            state.enter_alternate_screen(surface, true);
+        }
+        (DecPrivateModeCode::EnableAlternateScreen, false) => {
+            state.leave_alternate_screen(surface, false, false);
+        }
+        (DecPrivateModeCode::OptEnableAlternateScreen, false) => {
+            state.leave_alternate_screen(surface, true, false);
+        }
+        (DecPrivateModeCode::ClearAndEnableAlternateScreen, false) => {
+            state.leave_alternate_screen(surface, false, true);
+        }
+        (DecPrivateModeCode::DecAnsiMode, _)
+        | (DecPrivateModeCode::Select132Columns, _)
+        | (DecPrivateModeCode::SmoothScroll, _)
+        | (DecPrivateModeCode::ReverseVideo, _)
+        | (DecPrivateModeCode::AutoRepeat, _)
+        | (DecPrivateModeCode::StartBlinkingCursor, _)
+        | (DecPrivateModeCode::ReverseWraparound, _)
+        | (DecPrivateModeCode::LeftRightMarginMode, _)
+        | (DecPrivateModeCode::SixelDisplayMode, _)
+        | (DecPrivateModeCode::MouseTracking, _)
+        | (DecPrivateModeCode::HighlightMouseTracking, _)
+        | (DecPrivateModeCode::ButtonEventMouse, _)
+        | (DecPrivateModeCode::AnyEventMouse, _)
+        | (DecPrivateModeCode::FocusTracking, _)
+        | (DecPrivateModeCode::Utf8Mouse, _)
+        | (DecPrivateModeCode::SGRMouse, _)
+        | (DecPrivateModeCode::SGRPixelsMouse, _)
+        | (DecPrivateModeCode::XTermMetaSendsEscape, _)
+        | (DecPrivateModeCode::XTermAltSendsEscape, _)
+        | (DecPrivateModeCode::SaveCursor, _)
+        | (DecPrivateModeCode::BracketedPaste, _)
+        | (DecPrivateModeCode::GraphemeClustering, _)
+        | (DecPrivateModeCode::UsePrivateColorRegistersForEachGraphic, _)
+        | (DecPrivateModeCode::SynchronizedOutput, _)
+        | (DecPrivateModeCode::MinTTYApplicationEscapeKeyMode, _)
+        | (DecPrivateModeCode::SixelScrollsRight, _)
+        | (DecPrivateModeCode::Win32InputMode, _) => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal_builder::ScreenBuffer;
+
+    fn apply_mode(
+        surface: &mut Surface,
+        state: &mut TerminalState,
+        code: DecPrivateModeCode,
+        set: bool,
+    ) {
+        let mode = if set {
+            Mode::SetDecPrivateMode(DecPrivateMode::Code(code))
+        } else {
+            Mode::ResetDecPrivateMode(DecPrivateMode::Code(code))
+        };
+        process_mode(surface, state, &mode);
+    }
+
+    #[test]
+    fn mode_1049_switches_and_restores_primary() {
+        let mut s = Surface::new(5, 2);
+        let mut state = TerminalState::default();
+        s.add_change("main");
+
+        apply_mode(
+            &mut s,
+            &mut state,
+            DecPrivateModeCode::ClearAndEnableAlternateScreen,
+            true,
+        );
+        s.add_change("\rtui");
+
+        apply_mode(
+            &mut s,
+            &mut state,
+            DecPrivateModeCode::ClearAndEnableAlternateScreen,
+            false,
+        );
+        assert!(s.screen_chars_to_string().starts_with("main "));
+        assert_eq!(s.cursor_position(), (4, 0));
+
+        let alternate = state.take_screen(s, ScreenBuffer::Alternate);
+        assert!(alternate.screen_chars_to_string().contains("tui"));
+    }
+
+    #[test]
+    fn mode_47_keeps_alternate_contents() {
+        let mut s = Surface::new(5, 1);
+        let mut state = TerminalState::default();
+
+        apply_mode(
+            &mut s,
+            &mut state,
+            DecPrivateModeCode::EnableAlternateScreen,
+            true,
+        );
+        s.add_change("ab");
+        apply_mode(
+            &mut s,
+            &mut state,
+            DecPrivateModeCode::EnableAlternateScreen,
+            false,
+        );
+        apply_mode(
+            &mut s,
+            &mut state,
+            DecPrivateModeCode::EnableAlternateScreen,
+            true,
+        );
+
+        assert!(s.screen_chars_to_string().starts_with("ab"));
+    }
+
+    #[test]
+    fn mode_1047_clears_alternate_on_exit() {
+        let mut s = Surface::new(5, 1);
+        let mut state = TerminalState::default();
+
+        apply_mode(
+            &mut s,
+            &mut state,
+            DecPrivateModeCode::OptEnableAlternateScreen,
+            true,
+        );
+        s.add_change("ab");
+        apply_mode(
+            &mut s,
+            &mut state,
+            DecPrivateModeCode::OptEnableAlternateScreen,
+            false,
+        );
+        apply_mode(
+            &mut s,
+            &mut state,
+            DecPrivateModeCode::OptEnableAlternateScreen,
+            true,
+        );
+
+        assert!(s.screen_chars_to_string().starts_with("     "));
+    }
+
+    #[test]
+    fn autowrap_and_cursor_visibility_modes() {
+        let mut s = Surface::new(5, 5);
+        let mut state = TerminalState::default();
+
+        apply_mode(&mut s, &mut state, DecPrivateModeCode::AutoWrap, false);
+        apply_mode(&mut s, &mut state, DecPrivateModeCode::ShowCursor, false);
+
+        assert!(!state.modes().autowrap);
+        assert!(!state.modes().cursor_visible);
+        assert_eq!(s.cursor_visibility(), CursorVisibility::Hidden);
+    }
+
+    #[test]
+    fn application_cursor_keys_mode() {
+        let mut s = Surface::new(5, 5);
+        let mut state = TerminalState::default();
+
+        apply_mode(
+            &mut s,
+            &mut state,
+            DecPrivateModeCode::ApplicationCursorKeys,
+            true,
+        );
+        assert!(state.modes().application_cursor_keys);
+
+        apply_mode(
+            &mut s,
+            &mut state,
+            DecPrivateModeCode::ApplicationCursorKeys,
+            false,
+        );
+        assert!(!state.modes().application_cursor_keys);
+    }
+
+    #[test]
+    fn origin_mode_homes_to_scroll_region() {
+        let mut s = Surface::new(5, 5);
+        let mut state = TerminalState::default();
+        state.set_scroll_region(&s, 2, 4);
+
+        apply_mode(&mut s, &mut state, DecPrivateModeCode::OriginMode, true);
+        assert_eq!(s.cursor_position(), (0, 2));
+
+        apply_mode(&mut s, &mut state, DecPrivateModeCode::OriginMode, false);
+        assert_eq!(s.cursor_position(), (0, 0));
+    }
+
+    #[test]
+    fn insert_mode_is_toggled_by_sm_and_rm() {
+        let mut s = Surface::new(5, 5);
+        let mut state = TerminalState::default();
+        let insert = TerminalMode::Code(TerminalModeCode::Insert);
+
+        process_mode(&mut s, &mut state, &Mode::SetMode(insert.clone()));
+        assert!(state.modes().insert);
+
+        process_mode(&mut s, &mut state, &Mode::ResetMode(insert));
+        assert!(!state.modes().insert);
+    }
+}
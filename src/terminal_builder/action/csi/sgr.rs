@@ -1,48 +1,55 @@
 use termwiz::{
-    cell::{AttributeChange, CellAttributes},
+    cell::AttributeChange,
     escape::csi::Sgr,
-    surface::{Change, SEQ_ZERO, SequenceNo, Surface},
+    surface::{SEQ_ZERO, SequenceNo, Surface},
 };
 
-pub fn process_sgr(surface: &mut Surface, sgr: &Sgr) -> SequenceNo {
+use crate::terminal_builder::state::TerminalState;
+
+pub fn process_sgr(surface: &mut Surface, state: &mut TerminalState, sgr: &Sgr) -> SequenceNo {
     match sgr {
-        Sgr::Reset => surface.add_change(Change::AllAttributes(CellAttributes::default())),
+        Sgr::Reset => state.reset_pen(surface),
         Sgr::Intensity(intensity) => {
-            surface.add_change(Change::Attribute(AttributeChange::Intensity(*intensity)))
+            state.apply_attribute(surface, AttributeChange::Intensity(*intensity))
         }
         Sgr::Underline(underline) => {
-            surface.add_change(Change::Attribute(AttributeChange::Underline(*underline)))
+            state.apply_attribute(surface, AttributeChange::Underline(*underline))
         }
-        Sgr::Inverse(inverse) => {
-            surface.add_change(Change::Attribute(AttributeChange::Reverse(*inverse)))
+        Sgr::Inverse(inverse) => state.apply_attribute(surface, AttributeChange::Reverse(*inverse)),
+        Sgr::Foreground(color) => {
+            state.apply_attribute(surface, AttributeChange::Foreground((*color).into()))
         }
-        Sgr::Foreground(color) => surface.add_change(Change::Attribute(
-            AttributeChange::Foreground((*color).into()),
-        )),
-        Sgr::Background(color) => surface.add_change(Change::Attribute(
-            AttributeChange::Background((*color).into()),
-        )),
-        Sgr::Italic(italic) => {
-            surface.add_change(Change::Attribute(AttributeChange::Italic(*italic)))
+        Sgr::Background(color) => {
+            state.apply_attribute(surface, AttributeChange::Background((*color).into()))
         }
+        Sgr::Italic(italic) => state.apply_attribute(surface, AttributeChange::Italic(*italic)),
         Sgr::StrikeThrough(enabled) => {
-            surface.add_change(Change::Attribute(AttributeChange::StrikeThrough(*enabled)))
+            state.apply_attribute(surface, AttributeChange::StrikeThrough(*enabled))
         }
         Sgr::Invisible(enabled) => {
-            surface.add_change(Change::Attribute(AttributeChange::Invisible(*enabled)))
+            state.apply_attribute(surface, AttributeChange::Invisible(*enabled))
+        }
+        Sgr::UnderlineColor(color) => {
+            let mut pen = state.pen().clone();
+            pen.set_underline_color(*color);
+            state.set_pen(surface, pen)
+        }
+        Sgr::Overline(enabled) => {
+            let mut pen = state.pen().clone();
+            pen.set_overline(*enabled);
+            state.set_pen(surface, pen)
         }
-        Sgr::UnderlineColor(_)
-        | Sgr::Blink(_)
-        | Sgr::Font(_)
-        | Sgr::Overline(_)
-        | Sgr::VerticalAlign(_) => SEQ_ZERO,
+        Sgr::Blink(blink) => state.apply_attribute(surface, AttributeChange::Blink(*blink)),
+        // Alternate fonts (SGR 11-19) are drawn with the primary font
+        Sgr::Font(_) | Sgr::VerticalAlign(_) => SEQ_ZERO,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use termwiz::cell::{Intensity, Underline};
+    use std::sync::Arc;
+    use termwiz::cell::{AttributeChange, Blink, Hyperlink, Intensity, Underline};
     use termwiz::color::{ColorAttribute, ColorSpec, SrgbaTuple};
     use termwiz::escape::csi::Sgr;
     use termwiz::surface::Surface;
@@ -52,7 +59,7 @@ mod tests {
     }
 
     fn apply_sgr(surface: &mut Surface, sgr: &Sgr) {
-        process_sgr(surface, sgr);
+        process_sgr(surface, &mut TerminalState::default(), sgr);
     }
 
     #[test]
@@ -69,6 +76,23 @@ mod tests {
         assert_eq!(s.cursor_shape(), None);
     }
 
+    #[test]
+    fn sgr_reset_keeps_hyperlink() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        let link = Arc::new(Hyperlink::new("https://example.com"));
+        state.apply_attribute(&mut s, AttributeChange::Hyperlink(Some(link.clone())));
+        process_sgr(&mut s, &mut state, &Sgr::Intensity(Intensity::Bold));
+
+        process_sgr(&mut s, &mut state, &Sgr::Reset);
+        s.add_change("A");
+
+        let screen = s.screen_cells();
+        let attrs = screen[0][0].attrs();
+        assert_eq!(attrs.intensity(), Intensity::Normal);
+        assert_eq!(attrs.hyperlink(), Some(&link));
+    }
+
     #[test]
     fn sgr_intensity() {
         let mut s = make_surface();
@@ -188,4 +212,34 @@ mod tests {
             ColorAttribute::TrueColorWithDefaultFallback(color)
         );
     }
+
+    #[test]
+    fn sgr_underline_color_overline_and_blink() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        process_sgr(&mut s, &mut state, &Sgr::Underline(Underline::Curly));
+        process_sgr(
+            &mut s,
+            &mut state,
+            &Sgr::UnderlineColor(ColorSpec::PaletteIndex(1)),
+        );
+        process_sgr(&mut s, &mut state, &Sgr::Overline(true));
+        process_sgr(&mut s, &mut state, &Sgr::Blink(Blink::Slow));
+
+        s.add_change("N");
+        let screen = s.screen_cells();
+        let attrs = screen[0][0].attrs();
+        assert_eq!(attrs.underline(), Underline::Curly);
+        assert_eq!(attrs.underline_color(), ColorAttribute::PaletteIndex(1));
+        assert!(attrs.overline());
+        assert_eq!(attrs.blink(), Blink::Slow);
+
+        process_sgr(&mut s, &mut state, &Sgr::Reset);
+        s.add_change("O");
+        let screen = s.screen_cells();
+        let attrs = screen[0][1].attrs();
+        assert_eq!(attrs.underline_color(), ColorAttribute::Default);
+        assert!(!attrs.overline());
+        assert_eq!(attrs.blink(), Blink::None);
+    }
 }
@@ -0,0 +1,86 @@
+use std::io;
+
+use termwiz::{
+    escape::csi::Window,
+    surface::{SEQ_ZERO, SequenceNo, Surface},
+};
+
+use crate::constants::{CELL_PIXEL_HEIGHT, CELL_PIXEL_WIDTH};
+
+/// Answers the size reports (XTWINOPS) programs use to scale the images they
+/// draw. Window manipulations do not apply to a capture and are ignored.
+pub fn process_window(
+    surface: &Surface,
+    writer: &mut dyn io::Write,
+    window: &Window,
+) -> SequenceNo {
+    let (cols, rows) = surface.dimensions();
+    let response = match window {
+        Window::ReportTextAreaSizePixels => format!(
+            "\x1b[4;{};{}t",
+            rows as u32 * CELL_PIXEL_HEIGHT,
+            cols as u32 * CELL_PIXEL_WIDTH
+        ),
+        Window::ReportCellSizePixels => {
+            format!("\x1b[6;{CELL_PIXEL_HEIGHT};{CELL_PIXEL_WIDTH}t")
+        }
+        Window::ReportTextAreaSizeCells => format!("\x1b[8;{rows};{cols}t"),
+        Window::DeIconify
+        | Window::Iconify
+        | Window::MoveWindow { .. }
+        | Window::ResizeWindowPixels { .. }
+        | Window::RaiseWindow
+        | Window::LowerWindow
+        | Window::RefreshWindow
+        | Window::ResizeWindowCells { .. }
+        | Window::RestoreMaximizedWindow
+        | Window::MaximizeWindow
+        | Window::MaximizeWindowVertically
+        | Window::MaximizeWindowHorizontally
+        | Window::UndoFullScreenMode
+        | Window::ChangeToFullScreenMode
+        | Window::ToggleFullScreen
+        | Window::ReportWindowState
+        | Window::ReportWindowPosition
+        | Window::ReportTextAreaPosition
+        | Window::ReportWindowSizePixels
+        | Window::ReportScreenSizePixels
+        | Window::ReportCellSizePixelsResponse { .. }
+        | Window::ReportScreenSizeCells
+        | Window::ReportIconLabel
+        | Window::ReportWindowTitle
+        | Window::PushIconAndWindowTitle
+        | Window::PushIconTitle
+        | Window::PushWindowTitle
+        | Window::PopIconAndWindowTitle
+        | Window::PopIconTitle
+        | Window::PopWindowTitle
+        | Window::ChecksumRectangularArea { .. } => return SEQ_ZERO,
+    };
+
+    write!(writer, "{response}").ok();
+    writer.flush().ok();
+    SEQ_ZERO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply_to(window: &Window) -> String {
+        let mut writer = Vec::new();
+        process_window(&Surface::new(80, 24), &mut writer, window);
+        String::from_utf8(writer).expect("reply should be UTF-8")
+    }
+
+    #[test]
+    fn test_size_reports() {
+        assert_eq!(reply_to(&Window::ReportCellSizePixels), "\x1b[6;20;11t");
+        assert_eq!(
+            reply_to(&Window::ReportTextAreaSizePixels),
+            "\x1b[4;480;880t"
+        );
+        assert_eq!(reply_to(&Window::ReportTextAreaSizeCells), "\x1b[8;24;80t");
+        assert_eq!(reply_to(&Window::RaiseWindow), "");
+    }
+}
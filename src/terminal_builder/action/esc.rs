@@ -0,0 +1,203 @@
+use termwiz::{
+    escape::{Esc, EscCode},
+    surface::{Change, Position, SEQ_ZERO, SequenceNo, Surface},
+};
+
+use crate::terminal_builder::{charset::CharacterSet, state::TerminalState};
+
+pub fn process_esc(surface: &mut Surface, state: &mut TerminalState, esc: &Esc) -> SequenceNo {
+    match esc {
+        Esc::Code(EscCode::Index) => state.line_feed(surface),
+        Esc::Code(EscCode::NextLine) => next_line(surface, state),
+        Esc::Code(EscCode::ReverseIndex) => state.reverse_index(surface),
+        Esc::Code(EscCode::HorizontalTabSet) => {
+            state.set_tab_stop(surface);
+            SEQ_ZERO
+        }
+        Esc::Code(EscCode::DecSaveCursorPosition) => {
+            state.save_cursor(surface);
+            SEQ_ZERO
+        }
+        Esc::Code(EscCode::DecRestoreCursorPosition) => state.restore_cursor(surface),
+        Esc::Code(EscCode::FullReset) => state.reset(surface),
+        Esc::Code(code @ (EscCode::AsciiCharacterSetG0 | EscCode::AsciiCharacterSetG1)) => {
+            designate(state, *code, CharacterSet::Ascii)
+        }
+        Esc::Code(code @ (EscCode::UkCharacterSetG0 | EscCode::UkCharacterSetG1)) => {
+            designate(state, *code, CharacterSet::Uk)
+        }
+        Esc::Code(code @ (EscCode::DecLineDrawingG0 | EscCode::DecLineDrawingG1)) => {
+            designate(state, *code, CharacterSet::DecLineDrawing)
+        }
+        Esc::Code(_) | Esc::Unspecified { .. } => SEQ_ZERO,
+    }
+}
+
+/// Moves the cursor to the start of the next line, scrolling like a line feed (NEL).
+pub fn next_line(surface: &mut Surface, state: &mut TerminalState) -> SequenceNo {
+    surface.add_change(Change::CursorPosition {
+        x: Position::Absolute(0),
+        y: Position::Relative(0),
+    });
+    state.line_feed(surface)
+}
+
+fn designate(state: &mut TerminalState, code: EscCode, charset: CharacterSet) -> SequenceNo {
+    let index = match code {
+        EscCode::AsciiCharacterSetG1 | EscCode::UkCharacterSetG1 | EscCode::DecLineDrawingG1 => 1,
+        _ => 0,
+    };
+    state.designate_charset(index, charset);
+    SEQ_ZERO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal_builder::action::{control::process_control, print::process_print_string};
+    use termwiz::{
+        cell::{AttributeChange, Intensity},
+        escape::ControlCode,
+    };
+
+    fn apply_esc(surface: &mut Surface, state: &mut TerminalState, code: EscCode) -> SequenceNo {
+        process_esc(surface, state, &Esc::Code(code))
+    }
+
+    #[test]
+    fn test_index_and_reverse_index() {
+        let mut s = Surface::new(5, 3);
+        let mut state = TerminalState::default();
+
+        apply_esc(&mut s, &mut state, EscCode::Index);
+        assert_eq!(s.cursor_position(), (0, 1));
+
+        apply_esc(&mut s, &mut state, EscCode::ReverseIndex);
+        assert_eq!(s.cursor_position(), (0, 0));
+    }
+
+    #[test]
+    fn test_reverse_index_at_region_top_scrolls_region() {
+        let mut s = Surface::new(1, 3);
+        let mut state = TerminalState::default();
+        s.add_change("A\r\nB\r\nC");
+        state.set_scroll_region(&s, 1, 3);
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(1),
+        });
+
+        apply_esc(&mut s, &mut state, EscCode::ReverseIndex);
+
+        assert_eq!(s.screen_chars_to_string(), "A\n \nB\n");
+    }
+
+    #[test]
+    fn test_dec_line_drawing_in_g0() {
+        let mut s = Surface::new(4, 1);
+        let mut state = TerminalState::default();
+
+        apply_esc(&mut s, &mut state, EscCode::DecLineDrawingG0);
+        process_print_string(&mut s, &mut state, "lqk");
+        apply_esc(&mut s, &mut state, EscCode::AsciiCharacterSetG0);
+        process_print_string(&mut s, &mut state, "q");
+
+        assert_eq!(s.screen_chars_to_string(), "┌─┐q\n");
+    }
+
+    #[test]
+    fn test_shift_out_invokes_g1() {
+        let mut s = Surface::new(3, 1);
+        let mut state = TerminalState::default();
+
+        apply_esc(&mut s, &mut state, EscCode::DecLineDrawingG1);
+        process_print_string(&mut s, &mut state, "x");
+        process_control(&mut s, &mut state, ControlCode::ShiftOut);
+        process_print_string(&mut s, &mut state, "x");
+        process_control(&mut s, &mut state, ControlCode::ShiftIn);
+        process_print_string(&mut s, &mut state, "x");
+
+        assert_eq!(s.screen_chars_to_string(), "x│x\n");
+    }
+
+    #[test]
+    fn test_next_line() {
+        let mut s = Surface::new(5, 2);
+        let mut state = TerminalState::default();
+        s.add_change("AB");
+
+        apply_esc(&mut s, &mut state, EscCode::NextLine);
+        assert_eq!(s.cursor_position(), (0, 1));
+
+        apply_esc(&mut s, &mut state, EscCode::NextLine);
+        assert_eq!(s.cursor_position(), (0, 1));
+        assert_eq!(s.screen_chars_to_string(), "     \n     \n");
+    }
+
+    #[test]
+    fn test_save_and_restore_cursor() {
+        let mut s = Surface::new(10, 3);
+        let mut state = TerminalState::default();
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(4),
+            y: Position::Absolute(1),
+        });
+        state.apply_attribute(&mut s, AttributeChange::Intensity(Intensity::Bold));
+        apply_esc(&mut s, &mut state, EscCode::DecLineDrawingG0);
+
+        apply_esc(&mut s, &mut state, EscCode::DecSaveCursorPosition);
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(0),
+        });
+        state.apply_attribute(&mut s, AttributeChange::Intensity(Intensity::Normal));
+        apply_esc(&mut s, &mut state, EscCode::AsciiCharacterSetG0);
+        apply_esc(&mut s, &mut state, EscCode::DecRestoreCursorPosition);
+
+        assert_eq!(s.cursor_position(), (4, 1));
+        process_print_string(&mut s, &mut state, "q");
+        let cell = &s.screen_cells()[1][4];
+        assert_eq!(cell.str(), "─");
+        assert_eq!(cell.attrs().intensity(), Intensity::Bold);
+    }
+
+    #[test]
+    fn test_restore_without_save_homes_cursor() {
+        let mut s = Surface::new(10, 3);
+        let mut state = TerminalState::default();
+        s.add_change("abc");
+
+        apply_esc(&mut s, &mut state, EscCode::DecRestoreCursorPosition);
+        assert_eq!(s.cursor_position(), (0, 0));
+    }
+
+    #[test]
+    fn test_horizontal_tab_set() {
+        let mut s = Surface::new(20, 1);
+        let mut state = TerminalState::default();
+        s.add_change("abc");
+
+        apply_esc(&mut s, &mut state, EscCode::HorizontalTabSet);
+        process_control(&mut s, &mut state, ControlCode::CarriageReturn);
+        process_control(&mut s, &mut state, ControlCode::HorizontalTab);
+
+        assert_eq!(s.cursor_position(), (3, 0));
+    }
+
+    #[test]
+    fn test_full_reset() {
+        let mut s = Surface::new(5, 3);
+        let mut state = TerminalState::default();
+        s.add_change("abc");
+        state.set_scroll_region(&s, 1, 3);
+        apply_esc(&mut s, &mut state, EscCode::DecLineDrawingG0);
+
+        apply_esc(&mut s, &mut state, EscCode::FullReset);
+
+        assert_eq!(s.cursor_position(), (0, 0));
+        assert_eq!(s.screen_chars_to_string(), "     \n     \n     \n");
+        assert_eq!(state.scroll_region(&s), 0..3);
+        process_print_string(&mut s, &mut state, "q");
+        assert_eq!(s.screen_cells()[0][0].str(), "q");
+    }
+}
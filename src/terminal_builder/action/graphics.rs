@@ -0,0 +1,588 @@
+use std::{io, sync::Arc};
+
+use image::{
+    RgbaImage,
+    imageops::{self, FilterType},
+};
+use termwiz::{
+    escape::{
+        KittyImage, Sixel,
+        apc::{KittyImagePlacement, KittyImageTransmit, KittyImageVerbosity},
+        osc::{ITermDimension, ITermFileData},
+    },
+    image::{ImageData, ImageDataType, TextureCoordinate},
+    surface::{Change, Position, SEQ_ZERO, SequenceNo, Surface, change::Image},
+};
+use tracing::warn;
+
+use crate::{
+    constants::{CELL_PIXEL_HEIGHT, CELL_PIXEL_WIDTH},
+    terminal_builder::{
+        kitty::{KittyError, decode_kitty_image, kitty_reply},
+        sixel::decode_sixel,
+        state::TerminalState,
+    },
+};
+
+/// Where the cursor ends once an image is placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageCursor {
+    /// On the line below the image, in the column it started from
+    Below,
+    /// In the cell after the bottom right corner of the image
+    After,
+    /// Back on the top left corner of the image
+    Unmoved,
+}
+
+pub fn process_sixel(
+    surface: &mut Surface,
+    state: &mut TerminalState,
+    sixel: &Sixel,
+) -> SequenceNo {
+    match decode_sixel(
+        sixel,
+        state.theme().background_color,
+        screen_pixels(surface),
+    ) {
+        Some(image) => place_image(surface, state, image, ImageCursor::Below),
+        None => SEQ_ZERO,
+    }
+}
+
+/// Handles the kitty graphics protocol: images are transmitted directly in
+/// the escape sequence, then displayed at the cursor. Animation frames are
+/// ignored.
+pub fn process_kitty_image(
+    surface: &mut Surface,
+    state: &mut TerminalState,
+    writer: &mut dyn io::Write,
+    kitty_image: &KittyImage,
+) -> SequenceNo {
+    match kitty_image {
+        KittyImage::TransmitData {
+            transmit,
+            verbosity,
+        } => receive_kitty_image(surface, state, writer, transmit, None, *verbosity),
+        KittyImage::TransmitDataAndDisplay {
+            transmit,
+            placement,
+            verbosity,
+        } => receive_kitty_image(
+            surface,
+            state,
+            writer,
+            transmit,
+            Some(placement),
+            *verbosity,
+        ),
+        KittyImage::Display {
+            image_id,
+            image_number,
+            placement,
+            verbosity,
+        } => {
+            let image = state
+                .kitty_images_mut()
+                .get(*image_id, *image_number)
+                .cloned();
+            let (seq, result) = match image {
+                Ok(image) => (
+                    display_kitty_image(surface, state, image, placement),
+                    Ok(()),
+                ),
+                Err(err) => (SEQ_ZERO, Err(err)),
+            };
+            reply_kitty(writer, *image_id, *image_number, *verbosity, &result);
+            seq
+        }
+        KittyImage::Query { transmit } => {
+            let result = state
+                .kitty_images_mut()
+                .receive(transmit, None, KittyImageVerbosity::Verbose)
+                .and_then(|transmission| match transmission {
+                    Some(transmission) => decode_kitty_image(&transmission).map(|_| ()),
+                    None => Ok(()),
+                });
+            reply_kitty(
+                writer,
+                transmit.image_id,
+                transmit.image_number,
+                kitty_image.verbosity(),
+                &result,
+            );
+            SEQ_ZERO
+        }
+        KittyImage::Delete { what, .. } => {
+            state.kitty_images_mut().delete(what);
+            SEQ_ZERO
+        }
+        KittyImage::TransmitFrame { .. } | KittyImage::ComposeFrame { .. } => SEQ_ZERO,
+    }
+}
+
+/// Collects a transmitted chunk, then decodes, stores and, for `a=T`,
+/// displays the image once the last chunk arrives.
+fn receive_kitty_image(
+    surface: &mut Surface,
+    state: &mut TerminalState,
+    writer: &mut dyn io::Write,
+    transmit: &KittyImageTransmit,
+    placement: Option<&KittyImagePlacement>,
+    verbosity: KittyImageVerbosity,
+) -> SequenceNo {
+    let transmission = match state
+        .kitty_images_mut()
+        .receive(transmit, placement, verbosity)
+    {
+        Ok(Some(transmission)) => transmission,
+        Ok(None) => return SEQ_ZERO,
+        Err(err) => {
+            reply_kitty(
+                writer,
+                transmit.image_id,
+                transmit.image_number,
+                verbosity,
+                &Err(err),
+            );
+            return SEQ_ZERO;
+        }
+    };
+
+    let mut image_id = transmission.transmit.image_id;
+    let (seq, result) = match decode_kitty_image(&transmission) {
+        Ok(image) => {
+            let seq = match &transmission.placement {
+                Some(placement) => display_kitty_image(surface, state, image.clone(), placement),
+                None => SEQ_ZERO,
+            };
+            image_id = state
+                .kitty_images_mut()
+                .store(&transmission.transmit, image);
+            (seq, Ok(()))
+        }
+        Err(err) => (SEQ_ZERO, Err(err)),
+    };
+    reply_kitty(
+        writer,
+        image_id,
+        transmission.transmit.image_number,
+        transmission.verbosity,
+        &result,
+    );
+    seq
+}
+
+/// Places the part of `image` selected by `placement`, scaled to its columns
+/// and rows when given.
+fn display_kitty_image(
+    surface: &mut Surface,
+    state: &mut TerminalState,
+    image: RgbaImage,
+    placement: &KittyImagePlacement,
+) -> SequenceNo {
+    let (width, height) = image.dimensions();
+    let x = placement.x.unwrap_or(0).min(width);
+    let y = placement.y.unwrap_or(0).min(height);
+    let w = placement
+        .w
+        .filter(|&w| w > 0)
+        .unwrap_or(width)
+        .min(width - x);
+    let h = placement
+        .h
+        .filter(|&h| h > 0)
+        .unwrap_or(height)
+        .min(height - y);
+    let image = imageops::crop_imm(&image, x, y, w, h).to_image();
+
+    let image = scale_image(
+        image,
+        placement
+            .columns
+            .filter(|&c| c > 0)
+            .map(|c| c.saturating_mul(CELL_PIXEL_WIDTH)),
+        placement
+            .rows
+            .filter(|&r| r > 0)
+            .map(|r| r.saturating_mul(CELL_PIXEL_HEIGHT)),
+        false,
+        screen_pixels(surface),
+    );
+    let cursor = if placement.do_not_move_cursor {
+        ImageCursor::Unmoved
+    } else {
+        ImageCursor::After
+    };
+    place_image(surface, state, image, cursor)
+}
+
+fn reply_kitty(
+    writer: &mut dyn io::Write,
+    image_id: Option<u32>,
+    image_number: Option<u32>,
+    verbosity: KittyImageVerbosity,
+    result: &Result<(), KittyError>,
+) {
+    if let Some(reply) = kitty_reply(image_id, image_number, verbosity, result) {
+        write!(writer, "{reply}").ok();
+        writer.flush().ok();
+    }
+}
+
+/// Displays an inline file sent with the iTerm2 `OSC 1337 ; File` sequence.
+/// Files that are not inline would be downloads and are ignored.
+pub fn process_iterm_file(
+    surface: &mut Surface,
+    state: &mut TerminalState,
+    file: &ITermFileData,
+) -> SequenceNo {
+    if !file.inline {
+        return SEQ_ZERO;
+    }
+    let image = match image::load_from_memory(&file.data) {
+        Ok(image) => image.to_rgba8(),
+        Err(err) => {
+            warn!("Failed to decode inline image: {err}");
+            return SEQ_ZERO;
+        }
+    };
+
+    let (screen_width, screen_height) = screen_pixels(surface);
+    let available = (surface
+        .dimensions()
+        .0
+        .saturating_sub(surface.cursor_position().0) as u32)
+        .saturating_mul(CELL_PIXEL_WIDTH);
+    let mut width = iterm_pixels(file.width, screen_width, CELL_PIXEL_WIDTH);
+    let height = iterm_pixels(file.height, screen_height, CELL_PIXEL_HEIGHT);
+    if width.is_none() && height.is_none() && image.width() > available {
+        width = Some(available);
+    }
+
+    let image = scale_image(
+        image,
+        width,
+        height,
+        file.preserve_aspect_ratio,
+        (screen_width, screen_height),
+    );
+    let cursor = if file.do_not_move_cursor {
+        ImageCursor::Unmoved
+    } else {
+        ImageCursor::After
+    };
+    place_image(surface, state, image, cursor)
+}
+
+/// Pixels an iTerm2 image dimension stands for, `None` when automatic.
+fn iterm_pixels(dimension: ITermDimension, screen: u32, cell: u32) -> Option<u32> {
+    let pixels = match dimension {
+        ITermDimension::Automatic => return None,
+        ITermDimension::Cells(cells) => u32::try_from(cells).ok()?.saturating_mul(cell),
+        ITermDimension::Pixels(pixels) => u32::try_from(pixels).ok()?,
+        ITermDimension::Percent(percent) => {
+            u32::try_from(percent).ok()?.saturating_mul(screen) / 100
+        }
+    };
+    (pixels > 0).then_some(pixels)
+}
+
+/// Pixels the whole screen of `surface` covers
+fn screen_pixels(surface: &Surface) -> (u32, u32) {
+    let (cols, rows) = surface.dimensions();
+    (
+        (cols as u32).saturating_mul(CELL_PIXEL_WIDTH),
+        (rows as u32).saturating_mul(CELL_PIXEL_HEIGHT),
+    )
+}
+
+/// Scales `image` to `width` by `height` pixels, each side at most the one of
+/// `max_size`. A missing side follows the aspect ratio of the image, which
+/// `preserve_aspect_ratio` also keeps when both are given by fitting the image
+/// inside them.
+fn scale_image(
+    image: RgbaImage,
+    width: Option<u32>,
+    height: Option<u32>,
+    preserve_aspect_ratio: bool,
+    (max_width, max_height): (u32, u32),
+) -> RgbaImage {
+    let (image_width, image_height) = image.dimensions();
+    if image_width == 0 || image_height == 0 {
+        return image;
+    }
+    let width = width.map(|width| width.min(max_width));
+    let height = height.map(|height| height.min(max_height));
+    let proportional = |side: u32, from: u32, to: u32, max: u32| {
+        (u64::from(side) * u64::from(to) / u64::from(from))
+            .min(u64::from(max))
+            .max(1) as u32
+    };
+
+    let (width, height) = match (width, height) {
+        (None, None) => return image,
+        (Some(width), None) => (
+            width,
+            proportional(image_height, image_width, width, max_height),
+        ),
+        (None, Some(height)) => (
+            proportional(image_width, image_height, height, max_width),
+            height,
+        ),
+        (Some(width), Some(height))
+            if preserve_aspect_ratio
+                && u64::from(width) * u64::from(image_height)
+                    > u64::from(height) * u64::from(image_width) =>
+        {
+            (
+                proportional(image_width, image_height, height, max_width),
+                height,
+            )
+        }
+        (Some(width), Some(_)) if preserve_aspect_ratio => (
+            width,
+            proportional(image_height, image_width, width, max_height),
+        ),
+        (Some(width), Some(height)) => (width, height),
+    };
+    if width == 0 || height == 0 {
+        return image;
+    }
+
+    if (width, height) == (image_width, image_height) {
+        image
+    } else {
+        imageops::resize(&image, width, height, FilterType::Triangle)
+    }
+}
+
+/// Attaches `image` to the cells from the cursor onward, one `CELL_PIXEL_WIDTH`
+/// by `CELL_PIXEL_HEIGHT` slice per cell, scrolling like printed lines would.
+/// The image is clipped at the right margin and at the height of the screen,
+/// and the cursor ends where `cursor` says.
+pub fn place_image(
+    surface: &mut Surface,
+    state: &mut TerminalState,
+    image: RgbaImage,
+    cursor: ImageCursor,
+) -> SequenceNo {
+    let (width, height) = image.dimensions();
+    let (x, _) = surface.cursor_position();
+    let cols =
+        (width.div_ceil(CELL_PIXEL_WIDTH) as usize).min(surface.dimensions().0.saturating_sub(x));
+    let rows = (height.div_ceil(CELL_PIXEL_HEIGHT) as usize).min(surface.dimensions().1);
+    if cols == 0 || rows == 0 {
+        return SEQ_ZERO;
+    }
+
+    let data = Arc::new(ImageData::with_data(ImageDataType::new_single_frame(
+        width,
+        height,
+        image.into_raw(),
+    )));
+    let cell_width = CELL_PIXEL_WIDTH as f32 / width as f32;
+    let cell_height = CELL_PIXEL_HEIGHT as f32 / height as f32;
+
+    for row in 0..rows {
+        if row > 0 {
+            state.line_feed(surface);
+        }
+        surface.add_change(Change::CursorPosition {
+            x: Position::Absolute(x),
+            y: Position::Relative(0),
+        });
+        surface.add_change(Change::Image(Image {
+            width: cols,
+            height: 1,
+            top_left: TextureCoordinate::new_f32(0.0, row as f32 * cell_height),
+            bottom_right: TextureCoordinate::new_f32(
+                cols as f32 * cell_width,
+                (row + 1) as f32 * cell_height,
+            ),
+            image: data.clone(),
+        }));
+    }
+
+    let (_, bottom) = surface.cursor_position();
+    match cursor {
+        ImageCursor::Below => {
+            state.line_feed(surface);
+            surface.add_change(Change::CursorPosition {
+                x: Position::Absolute(x),
+                y: Position::Relative(0),
+            })
+        }
+        ImageCursor::After => surface.add_change(Change::CursorPosition {
+            x: Position::Absolute(x + cols),
+            y: Position::Relative(0),
+        }),
+        ImageCursor::Unmoved => surface.add_change(Change::CursorPosition {
+            x: Position::Absolute(x),
+            y: Position::Absolute((bottom + 1).saturating_sub(rows)),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal_builder::action::process_action;
+    use image::Rgba;
+    use termwiz::escape::parser::Parser;
+
+    fn feed(s: &mut Surface, state: &mut TerminalState, input: &str) -> String {
+        let mut writer = Vec::new();
+        for action in Parser::new().parse_as_vec(input.as_bytes()) {
+            process_action(s, state, &mut writer, &action);
+        }
+        String::from_utf8(writer).expect("reply should be UTF-8")
+    }
+
+    fn has_image(s: &mut Surface, row: usize, col: usize) -> bool {
+        s.screen_cells()[row][col].attrs().images().is_some()
+    }
+
+    #[test]
+    fn test_place_image_covers_cells() {
+        let mut s = Surface::new(10, 4);
+        let mut state = TerminalState::default();
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(2),
+            y: Position::Absolute(0),
+        });
+        let image = RgbaImage::from_pixel(
+            CELL_PIXEL_WIDTH * 2,
+            CELL_PIXEL_HEIGHT + 1,
+            Rgba([255, 0, 0, 255]),
+        );
+
+        place_image(&mut s, &mut state, image, ImageCursor::Below);
+
+        let screen = s.screen_cells();
+        let has_image = |row: usize, col: usize| screen[row][col].attrs().images().is_some();
+        assert!(has_image(0, 2) && has_image(0, 3) && has_image(1, 3));
+        assert!(!has_image(0, 1) && !has_image(0, 4) && !has_image(2, 2));
+        assert_eq!(s.cursor_position(), (2, 2));
+    }
+
+    #[test]
+    fn test_place_image_scrolls_at_bottom() {
+        let mut s = Surface::new(4, 2);
+        let mut state = TerminalState::default();
+        s.add_change("top");
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(1),
+        });
+        let image = RgbaImage::new(CELL_PIXEL_WIDTH, CELL_PIXEL_HEIGHT * 2);
+
+        place_image(&mut s, &mut state, image, ImageCursor::Below);
+
+        assert_eq!(state.take_scrollback().len(), 2);
+        assert!(s.screen_cells()[0][0].attrs().images().is_some());
+    }
+
+    #[test]
+    fn test_kitty_query_is_answered() {
+        let mut s = Surface::new(10, 4);
+        let mut state = TerminalState::default();
+        let reply = feed(
+            &mut s,
+            &mut state,
+            "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\",
+        );
+        assert_eq!(reply, "\x1b_Gi=31;OK\x1b\\");
+        assert!(!has_image(&mut s, 0, 0));
+    }
+
+    #[test]
+    fn test_kitty_transmit_in_chunks_and_display() {
+        let mut s = Surface::new(10, 4);
+        let mut state = TerminalState::default();
+        // A 2x1 RGB image sent in two chunks, then displayed over 3 columns
+        let reply = feed(
+            &mut s,
+            &mut state,
+            "\x1b_Ga=t,f=24,s=2,v=1,i=5,q=1,m=1;/wAA\x1b\\\x1b_Gm=0;AP8A\x1b\\\x1b_Ga=p,i=5,c=3\x1b\\",
+        );
+        assert_eq!(reply, "\x1b_Gi=5;OK\x1b\\");
+        assert!(has_image(&mut s, 0, 0) && has_image(&mut s, 0, 2) && !has_image(&mut s, 0, 3));
+        assert_eq!(s.cursor_position(), (3, 0));
+    }
+
+    #[test]
+    fn test_kitty_display_unknown_image() {
+        let mut s = Surface::new(10, 4);
+        let mut state = TerminalState::default();
+        let reply = feed(&mut s, &mut state, "\x1b_Ga=p,i=9\x1b\\");
+        assert_eq!(reply, "\x1b_Gi=9;ENOENT:image not found\x1b\\");
+    }
+
+    #[test]
+    fn test_iterm_inline_image() {
+        let mut s = Surface::new(10, 4);
+        let mut state = TerminalState::default();
+        let mut png = Vec::new();
+        RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255]))
+            .write_to(&mut io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("encode");
+        let file = ITermFileData {
+            name: None,
+            size: None,
+            width: ITermDimension::Cells(2),
+            height: ITermDimension::Automatic,
+            preserve_aspect_ratio: true,
+            inline: true,
+            do_not_move_cursor: false,
+            data: png,
+        };
+
+        process_iterm_file(&mut s, &mut state, &file);
+
+        assert!(has_image(&mut s, 0, 0) && has_image(&mut s, 0, 1) && has_image(&mut s, 1, 1));
+        assert!(!has_image(&mut s, 0, 2) && !has_image(&mut s, 2, 0));
+        assert_eq!(s.cursor_position(), (2, 1));
+    }
+
+    #[test]
+    fn test_scale_image() {
+        let image = RgbaImage::new(40, 20);
+        let size =
+            |w, h, preserve| scale_image(image.clone(), w, h, preserve, (1000, 1000)).dimensions();
+        assert_eq!(size(None, None, true), (40, 20));
+        assert_eq!(size(Some(20), None, false), (20, 10));
+        assert_eq!(size(None, Some(40), false), (80, 40));
+        assert_eq!(size(Some(20), Some(20), false), (20, 20));
+        assert_eq!(size(Some(20), Some(20), true), (20, 10));
+        assert_eq!(size(Some(100), Some(10), true), (20, 10));
+    }
+
+    #[test]
+    fn test_oversized_images_are_clamped() {
+        let image = RgbaImage::new(40, 20);
+        let size = |w, h| scale_image(image.clone(), w, h, false, (100, 60)).dimensions();
+        assert_eq!(size(Some(u32::MAX), None), (100, 50));
+        assert_eq!(size(None, Some(u32::MAX)), (100, 60));
+        assert_eq!(size(Some(u32::MAX), Some(u32::MAX)), (100, 60));
+
+        let mut s = Surface::new(10, 4);
+        let mut state = TerminalState::default();
+        feed(
+            &mut s,
+            &mut state,
+            "\x1b_Ga=T,f=24,s=1,v=1,c=60000,r=60000;AAAA\x1b\\",
+        );
+        assert!(has_image(&mut s, 0, 0) && has_image(&mut s, 3, 9));
+        assert!(state.take_scrollback().is_empty());
+    }
+
+    #[test]
+    fn test_iterm_dimensions() {
+        assert_eq!(iterm_pixels(ITermDimension::Automatic, 100, 10), None);
+        assert_eq!(iterm_pixels(ITermDimension::Cells(3), 100, 10), Some(30));
+        assert_eq!(iterm_pixels(ITermDimension::Pixels(7), 100, 10), Some(7));
+        assert_eq!(iterm_pixels(ITermDimension::Percent(50), 100, 10), Some(50));
+        assert_eq!(iterm_pixels(ITermDimension::Pixels(-1), 100, 10), None);
+    }
+}
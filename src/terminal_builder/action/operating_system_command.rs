@@ -1,59 +1,184 @@
-use std::io::{self};
+use std::{
+    io::{self},
+    sync::Arc,
+};
 
+use image::Rgba;
 use num_traits::FromPrimitive;
 use termwiz::{
-    cell::AttributeChange,
-    color::ColorAttribute,
+    cell::{AttributeChange, SemanticType},
+    color::{ColorAttribute, SrgbaTuple},
     escape::{
         OperatingSystemCommand,
-        osc::{ColorOrQuery, DynamicColorNumber},
+        osc::{
+            ChangeColorPair, ColorOrQuery, DynamicColorNumber, FinalTermSemanticPrompt,
+            ITermProprietary,
+        },
     },
-    surface::{Change, SEQ_ZERO, SequenceNo, Surface},
+    surface::{Change, Position, SEQ_ZERO, SequenceNo, Surface},
+};
+
+use crate::{
+    constants::{CELL_PIXEL_HEIGHT, CELL_PIXEL_WIDTH},
+    terminal_builder::{action::graphics::process_iterm_file, state::TerminalState},
 };
 
 pub fn process_operating_system_command(
     surface: &mut Surface,
+    state: &mut TerminalState,
     writer: &mut dyn io::Write,
     operating_system_command: &OperatingSystemCommand,
 ) -> SequenceNo {
     match operating_system_command {
         OperatingSystemCommand::ChangeDynamicColors(dynamic_color_number, items) => {
-            process_change_dynamic_colors(surface, writer, *dynamic_color_number, items.clone())
+            process_change_dynamic_colors(
+                surface,
+                state,
+                writer,
+                *dynamic_color_number,
+                items.clone(),
+            )
         }
         OperatingSystemCommand::ResetDynamicColor(dynamic_color_number) => {
-            process_reset_dynamic_color(surface, *dynamic_color_number)
+            process_reset_dynamic_color(surface, state, *dynamic_color_number)
+        }
+        OperatingSystemCommand::ChangeColorNumber(pairs) => {
+            process_change_color_number(state, writer, pairs)
         }
         OperatingSystemCommand::ResetColors(items) => {
+            if items.is_empty() {
+                state.reset_palette();
+            }
+            // Only palette entries: dynamic colors are reset by OSC 110 and 111
             for byte in items {
-                if let Some(color) = &FromPrimitive::from_u8(*byte) {
-                    process_reset_dynamic_color(surface, *color);
-                }
+                state.reset_palette_color(*byte);
             }
 
             SEQ_ZERO
         }
-        OperatingSystemCommand::SetIconNameAndWindowTitle(_)
-        | OperatingSystemCommand::SetWindowTitle(_)
-        | OperatingSystemCommand::SetWindowTitleSun(_)
-        | OperatingSystemCommand::SetIconName(_)
+        OperatingSystemCommand::SetHyperlink(link) => state.apply_attribute(
+            surface,
+            AttributeChange::Hyperlink(link.clone().map(Arc::new)),
+        ),
+        OperatingSystemCommand::SetIconNameAndWindowTitle(title)
+        | OperatingSystemCommand::SetWindowTitle(title)
+        | OperatingSystemCommand::SetWindowTitleSun(title) => {
+            state.set_title(title);
+            SEQ_ZERO
+        }
+        OperatingSystemCommand::FinalTermSemanticPrompt(prompt) => {
+            process_semantic_prompt(surface, state, prompt)
+        }
+        OperatingSystemCommand::ConEmuProgress(progress) => {
+            state.report_progress(progress);
+            SEQ_ZERO
+        }
+        OperatingSystemCommand::ITermProprietary(ITermProprietary::File(file)) => {
+            process_iterm_file(surface, state, file)
+        }
+        OperatingSystemCommand::ITermProprietary(ITermProprietary::RequestCellSize) => {
+            write!(
+                writer,
+                "\x1b]1337;ReportCellSize={CELL_PIXEL_HEIGHT}.0;{CELL_PIXEL_WIDTH}.0\x1b\\"
+            )
+            .ok();
+            writer.flush().ok();
+            SEQ_ZERO
+        }
+        OperatingSystemCommand::SetIconName(_)
         | OperatingSystemCommand::SetIconNameSun(_)
-        | OperatingSystemCommand::SetHyperlink(_)
         | OperatingSystemCommand::ClearSelection(_)
         | OperatingSystemCommand::QuerySelection(_)
         | OperatingSystemCommand::SetSelection(_, _)
         | OperatingSystemCommand::SystemNotification(_)
         | OperatingSystemCommand::ITermProprietary(_)
-        | OperatingSystemCommand::FinalTermSemanticPrompt(_)
-        | OperatingSystemCommand::ChangeColorNumber(_)
         | OperatingSystemCommand::CurrentWorkingDirectory(_)
         | OperatingSystemCommand::RxvtExtension(_)
-        | OperatingSystemCommand::ConEmuProgress(_)
         | OperatingSystemCommand::Unspecified(_) => SEQ_ZERO,
     }
 }
 
+/// Tags the cells printed after an OSC 133 mark as prompt, typed command or
+/// command output, and records where each command starts and ends.
+fn process_semantic_prompt(
+    surface: &mut Surface,
+    state: &mut TerminalState,
+    prompt: &FinalTermSemanticPrompt,
+) -> SequenceNo {
+    match prompt {
+        FinalTermSemanticPrompt::FreshLine => fresh_line(surface, state),
+        FinalTermSemanticPrompt::FreshLineAndStartPrompt { .. } => {
+            fresh_line(surface, state);
+            state.mark_prompt(surface);
+            state.set_semantic_type(surface, SemanticType::Prompt)
+        }
+        FinalTermSemanticPrompt::MarkEndOfCommandWithFreshLine { .. } => {
+            state.mark_command_end(surface);
+            fresh_line(surface, state);
+            state.mark_prompt(surface);
+            state.set_semantic_type(surface, SemanticType::Prompt)
+        }
+        FinalTermSemanticPrompt::StartPrompt(_) => {
+            state.set_semantic_type(surface, SemanticType::Prompt)
+        }
+        FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilNextMarker
+        | FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilEndOfLine => {
+            state.set_semantic_type(surface, SemanticType::Input)
+        }
+        FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { .. } => {
+            state.mark_command_output(surface);
+            state.set_semantic_type(surface, SemanticType::Output)
+        }
+        FinalTermSemanticPrompt::CommandStatus { .. } => {
+            state.mark_command_end(surface);
+            state.set_semantic_type(surface, SemanticType::Output)
+        }
+    }
+}
+
+/// Moves the cursor to the start of the next line unless it already is at
+/// the start of a line.
+fn fresh_line(surface: &mut Surface, state: &mut TerminalState) -> SequenceNo {
+    if surface.cursor_position().0 == 0 {
+        return SEQ_ZERO;
+    }
+    surface.add_change(Change::CursorPosition {
+        x: Position::Absolute(0),
+        y: Position::Relative(0),
+    });
+    state.line_feed(surface)
+}
+
+/// Redefines palette entries (OSC 4), or reports them when queried.
+fn process_change_color_number(
+    state: &mut TerminalState,
+    writer: &mut dyn io::Write,
+    pairs: &[ChangeColorPair],
+) -> SequenceNo {
+    for pair in pairs {
+        match pair.color {
+            ColorOrQuery::Color(color) => {
+                state.set_palette_color(pair.palette_index, rgba(color));
+            }
+            ColorOrQuery::Query => {
+                let color = state.theme().palette[usize::from(pair.palette_index)];
+                reply(
+                    writer,
+                    &OperatingSystemCommand::ChangeColorNumber(vec![ChangeColorPair {
+                        palette_index: pair.palette_index,
+                        color: ColorOrQuery::Color(srgba(color)),
+                    }]),
+                );
+            }
+        }
+    }
+
+    SEQ_ZERO
+}
+
 fn process_change_dynamic_colors(
     surface: &mut Surface,
+    state: &mut TerminalState,
     writer: &mut dyn io::Write,
     first_color: DynamicColorNumber,
     colors: Vec<ColorOrQuery>,
@@ -67,13 +192,27 @@ fn process_change_dynamic_colors(
         })
         .for_each(|(target, color)| match target {
             DynamicColorNumber::TextForegroundColor => {
-                if let Some(attr) = color_or_query(writer, target, color) {
-                    surface.add_change(Change::Attribute(AttributeChange::Foreground(attr)));
+                let current = state.theme().foreground_color;
+                if let Some(color) = color_or_query(writer, target, color, current) {
+                    state.set_foreground_color(rgba(color));
+                    state.apply_attribute(
+                        surface,
+                        AttributeChange::Foreground(ColorAttribute::TrueColorWithDefaultFallback(
+                            color,
+                        )),
+                    );
                 }
             }
             DynamicColorNumber::TextBackgroundColor => {
-                if let Some(attr) = color_or_query(writer, target, color) {
-                    surface.add_change(Change::Attribute(AttributeChange::Background(attr)));
+                let current = state.theme().background_color;
+                if let Some(color) = color_or_query(writer, target, color, current) {
+                    state.set_background_color(rgba(color));
+                    state.apply_attribute(
+                        surface,
+                        AttributeChange::Background(ColorAttribute::TrueColorWithDefaultFallback(
+                            color,
+                        )),
+                    );
                 }
             }
             DynamicColorNumber::TextCursorColor
@@ -91,18 +230,27 @@ fn process_change_dynamic_colors(
 
 fn process_reset_dynamic_color(
     surface: &mut Surface,
+    state: &mut TerminalState,
     dynamic_color_number: DynamicColorNumber,
 ) -> SequenceNo {
     let idx: u8 = dynamic_color_number as u8;
 
     if let Some(which_color) = FromPrimitive::from_u8(idx) {
         return match which_color {
-            DynamicColorNumber::TextForegroundColor => surface.add_change(Change::Attribute(
-                AttributeChange::Foreground(ColorAttribute::Default),
-            )),
-            DynamicColorNumber::TextBackgroundColor => surface.add_change(Change::Attribute(
-                AttributeChange::Background(ColorAttribute::Default),
-            )),
+            DynamicColorNumber::TextForegroundColor => {
+                state.reset_foreground_color();
+                state.apply_attribute(
+                    surface,
+                    AttributeChange::Foreground(ColorAttribute::Default),
+                )
+            }
+            DynamicColorNumber::TextBackgroundColor => {
+                state.reset_background_color();
+                state.apply_attribute(
+                    surface,
+                    AttributeChange::Background(ColorAttribute::Default),
+                )
+            }
             DynamicColorNumber::TextCursorColor
             | DynamicColorNumber::MouseForegroundColor
             | DynamicColorNumber::MouseBackgroundColor
@@ -117,28 +265,49 @@ fn process_reset_dynamic_color(
     SEQ_ZERO
 }
 
+/// Color to set, or `None` after answering a query with the `current` color.
 fn color_or_query(
     writer: &mut dyn io::Write,
     target: DynamicColorNumber,
     color: ColorOrQuery,
-) -> Option<ColorAttribute> {
+    current: Rgba<u8>,
+) -> Option<SrgbaTuple> {
     match color {
-        ColorOrQuery::Color(c) => Some(ColorAttribute::TrueColorWithDefaultFallback(c)),
+        ColorOrQuery::Color(c) => Some(c),
         ColorOrQuery::Query => {
-            let response = OperatingSystemCommand::ChangeDynamicColors(target, vec![color]);
-            write!(writer, "{response}").ok();
-            writer.flush().ok();
+            let response = OperatingSystemCommand::ChangeDynamicColors(
+                target,
+                vec![ColorOrQuery::Color(srgba(current))],
+            );
+            reply(writer, &response);
             None
         }
     }
 }
 
+fn srgba(Rgba([r, g, b, a]): Rgba<u8>) -> SrgbaTuple {
+    SrgbaTuple::from((r, g, b, a))
+}
+
+fn rgba(color: SrgbaTuple) -> Rgba<u8> {
+    let (r, g, b, a) = color.to_srgb_u8();
+    Rgba([r, g, b, a])
+}
+
+fn reply(writer: &mut dyn io::Write, response: &OperatingSystemCommand) {
+    write!(writer, "{response}").ok();
+    writer.flush().ok();
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::terminal_builder::TaskProgress;
+    use crate::theme::Theme;
     use termwiz::escape::OperatingSystemCommand;
-    use termwiz::escape::osc::{ColorOrQuery, DynamicColorNumber};
+    use termwiz::escape::osc::{ColorOrQuery, DynamicColorNumber, Progress};
     use termwiz::{
+        cell::Hyperlink,
         color::{ColorAttribute, SrgbaTuple},
         surface::Surface,
     };
@@ -149,7 +318,7 @@ mod tests {
 
     fn apply_osc(surface: &mut Surface, osc: &OperatingSystemCommand) {
         let mut writer = std::io::sink();
-        process_operating_system_command(surface, &mut writer, osc);
+        process_operating_system_command(surface, &mut TerminalState::default(), &mut writer, osc);
     }
 
     #[test]
@@ -245,7 +414,7 @@ mod tests {
     }
 
     #[test]
-    fn test_reset_colors_multiple() {
+    fn test_reset_colors_keeps_dynamic_colors() {
         let mut s = make_surface();
         let color = SrgbaTuple(0.0, 1.0, 0.0, 1.0);
 
@@ -269,7 +438,10 @@ mod tests {
         let screen = s.screen_cells();
         let cell = &screen[0][0];
         assert_eq!(cell.attrs().foreground(), ColorAttribute::Default);
-        assert_eq!(cell.attrs().background(), ColorAttribute::Default);
+        assert_eq!(
+            cell.attrs().background(),
+            ColorAttribute::TrueColorWithDefaultFallback(color)
+        );
     }
 
     #[test]
@@ -291,4 +463,240 @@ mod tests {
         assert_eq!(cell.attrs().foreground(), ColorAttribute::Default);
         assert_eq!(cell.attrs().background(), ColorAttribute::Default);
     }
+
+    #[test]
+    fn test_hyperlink_is_attached_to_printed_cells() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        let link = Hyperlink::new("https://example.com");
+        let mut writer = std::io::sink();
+
+        process_operating_system_command(
+            &mut s,
+            &mut state,
+            &mut writer,
+            &OperatingSystemCommand::SetHyperlink(Some(link.clone())),
+        );
+        s.add_change("A");
+        process_operating_system_command(
+            &mut s,
+            &mut state,
+            &mut writer,
+            &OperatingSystemCommand::SetHyperlink(None),
+        );
+        s.add_change("B");
+
+        let screen = s.screen_cells();
+        assert_eq!(
+            screen[0][0].attrs().hyperlink().map(|l| l.uri()),
+            Some(link.uri())
+        );
+        assert!(screen[0][1].attrs().hyperlink().is_none());
+    }
+
+    #[test]
+    fn test_window_title_is_kept() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        let mut writer = std::io::sink();
+
+        process_operating_system_command(
+            &mut s,
+            &mut state,
+            &mut writer,
+            &OperatingSystemCommand::SetIconNameAndWindowTitle("first".to_string()),
+        );
+        process_operating_system_command(
+            &mut s,
+            &mut state,
+            &mut writer,
+            &OperatingSystemCommand::SetWindowTitle("vim main.rs".to_string()),
+        );
+
+        assert_eq!(state.title(), Some("vim main.rs"));
+    }
+
+    #[test]
+    fn test_progress_is_kept() {
+        let mut s = make_surface();
+        let mut state = TerminalState::default();
+        let mut writer = std::io::sink();
+
+        for progress in [Progress::SetPercentage(60), Progress::Paused] {
+            process_operating_system_command(
+                &mut s,
+                &mut state,
+                &mut writer,
+                &OperatingSystemCommand::ConEmuProgress(progress),
+            );
+        }
+
+        assert_eq!(state.progress(), TaskProgress::Paused(60));
+    }
+
+    #[test]
+    fn test_semantic_prompt_marks_cells() {
+        let mut s = Surface::new(10, 3);
+        let mut state = TerminalState::default();
+        let mut writer = std::io::sink();
+        let mut mark = |s: &mut Surface, prompt: FinalTermSemanticPrompt| {
+            process_operating_system_command(
+                s,
+                &mut state,
+                &mut writer,
+                &OperatingSystemCommand::FinalTermSemanticPrompt(prompt),
+            );
+        };
+
+        s.add_change("x");
+        mark(
+            &mut s,
+            FinalTermSemanticPrompt::FreshLineAndStartPrompt {
+                aid: None,
+                cl: None,
+            },
+        );
+        s.add_change("$");
+        mark(
+            &mut s,
+            FinalTermSemanticPrompt::MarkEndOfPromptAndStartOfInputUntilNextMarker,
+        );
+        s.add_change("ls");
+        mark(
+            &mut s,
+            FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { aid: None },
+        );
+        s.add_change("a");
+
+        let screen = s.screen_cells();
+        let types: Vec<_> = screen[1][..4]
+            .iter()
+            .map(|cell| cell.attrs().semantic_type())
+            .collect();
+        assert_eq!(
+            types,
+            [
+                SemanticType::Prompt,
+                SemanticType::Input,
+                SemanticType::Input,
+                SemanticType::Output
+            ]
+        );
+        assert_eq!(screen[0][0].attrs().semantic_type(), SemanticType::Output);
+    }
+
+    fn reply_to(state: &mut TerminalState, osc: &OperatingSystemCommand) -> String {
+        let mut writer = Vec::new();
+        process_operating_system_command(&mut make_surface(), state, &mut writer, osc);
+        String::from_utf8(writer).expect("reply should be UTF-8")
+    }
+
+    #[test]
+    fn test_dynamic_color_query_reports_theme_color() {
+        let mut state = TerminalState::default();
+        let background = reply_to(
+            &mut state,
+            &OperatingSystemCommand::ChangeDynamicColors(
+                DynamicColorNumber::TextBackgroundColor,
+                vec![ColorOrQuery::Query],
+            ),
+        );
+        assert_eq!(background, "\x1b]11;rgb:2828/2c2c/3434\x1b\\");
+    }
+
+    #[test]
+    fn test_dynamic_colors_are_changed_queried_and_reset() {
+        let mut state = TerminalState::default();
+        let color = SrgbaTuple::from((0x12u8, 0x34u8, 0x56u8, 0xffu8));
+        let change =
+            |target, color| OperatingSystemCommand::ChangeDynamicColors(target, vec![color]);
+
+        reply_to(
+            &mut state,
+            &change(
+                DynamicColorNumber::TextForegroundColor,
+                ColorOrQuery::Color(color),
+            ),
+        );
+        reply_to(
+            &mut state,
+            &change(
+                DynamicColorNumber::TextBackgroundColor,
+                ColorOrQuery::Color(color),
+            ),
+        );
+        assert_eq!(
+            state.theme().foreground_color,
+            Rgba([0x12, 0x34, 0x56, 0xff])
+        );
+        assert_eq!(
+            state.theme().background_color,
+            Rgba([0x12, 0x34, 0x56, 0xff])
+        );
+        assert_eq!(
+            reply_to(
+                &mut state,
+                &change(DynamicColorNumber::TextForegroundColor, ColorOrQuery::Query)
+            ),
+            "\x1b]10;rgb:1212/3434/5656\x1b\\"
+        );
+        assert_eq!(
+            reply_to(
+                &mut state,
+                &change(DynamicColorNumber::TextBackgroundColor, ColorOrQuery::Query)
+            ),
+            "\x1b]11;rgb:1212/3434/5656\x1b\\"
+        );
+
+        reply_to(
+            &mut state,
+            &OperatingSystemCommand::ResetDynamicColor(DynamicColorNumber::TextForegroundColor),
+        );
+        reply_to(
+            &mut state,
+            &OperatingSystemCommand::ResetDynamicColor(DynamicColorNumber::TextBackgroundColor),
+        );
+        assert_eq!(
+            state.theme().foreground_color,
+            Theme::default().foreground_color
+        );
+        assert_eq!(
+            state.theme().background_color,
+            Theme::default().background_color
+        );
+    }
+
+    #[test]
+    fn test_palette_color_is_changed_queried_and_reset() {
+        let mut state = TerminalState::default();
+        let red = SrgbaTuple::from((0x12u8, 0x34u8, 0x56u8, 0xffu8));
+        let set = |color| {
+            OperatingSystemCommand::ChangeColorNumber(vec![ChangeColorPair {
+                palette_index: 1,
+                color,
+            }])
+        };
+
+        reply_to(&mut state, &set(ColorOrQuery::Color(red)));
+        assert_eq!(state.theme().palette[1], Rgba([0x12, 0x34, 0x56, 0xff]));
+        assert_eq!(
+            reply_to(&mut state, &set(ColorOrQuery::Query)),
+            "\x1b]4;1;rgb:1212/3434/5656\x1b\\"
+        );
+
+        reply_to(&mut state, &OperatingSystemCommand::ResetColors(vec![]));
+        assert_eq!(state.theme().palette[1], Theme::default().palette[1]);
+    }
+
+    #[test]
+    fn test_iterm_cell_size_query() {
+        let mut state = TerminalState::default();
+        assert_eq!(
+            reply_to(
+                &mut state,
+                &OperatingSystemCommand::ITermProprietary(ITermProprietary::RequestCellSize)
+            ),
+            "\x1b]1337;ReportCellSize=20.0;11.0\x1b\\"
+        );
+    }
 }
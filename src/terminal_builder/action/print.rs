@@ -1,9 +1,170 @@
-use termwiz::surface::{SequenceNo, Surface};
+use termwiz::surface::{Change, Position, SEQ_ZERO, SequenceNo, Surface};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-pub fn process_print(surface: &mut Surface, ch: char) -> SequenceNo {
-    surface.add_change(ch)
+use crate::terminal_builder::{state::TerminalState, utils::insert_cells};
+
+pub fn process_print(surface: &mut Surface, state: &mut TerminalState, ch: char) -> SequenceNo {
+    process_print_string(surface, state, ch.encode_utf8(&mut [0; 4]))
+}
+
+/// Prints `text`, wrapping at the right margin through the scroll region instead of
+/// letting the surface scroll the whole screen.
+pub fn process_print_string(
+    surface: &mut Surface,
+    state: &mut TerminalState,
+    text: &str,
+) -> SequenceNo {
+    let mut seq = SEQ_ZERO;
+    let text = state.translate(text);
+    let mut rest = text.as_ref();
+
+    if let Some(start) = text.rfind(|c: char| c.width().unwrap_or(0) > 0) {
+        state.set_last_printed(&text[start..]);
+    }
+
+    while !rest.is_empty() {
+        let width = surface.dimensions().0;
+        let (x, y) = surface.cursor_position();
+        let modes = state.modes();
+
+        let (chunk, remainder) = split_at_width(rest, width.saturating_sub(x));
+        if chunk.is_empty() && x > 0 {
+            let x = if modes.autowrap {
+                0
+            } else {
+                // Without autowrap, characters past the margin overwrite the last column
+                width.saturating_sub(first_char_width(rest))
+            };
+            surface.add_change(Change::CursorPosition {
+                x: Position::Absolute(x),
+                y: Position::Relative(0),
+            });
+            if modes.autowrap {
+                state.line_feed(surface);
+            }
+            continue;
+        }
+
+        // A character wider than the whole line is printed on its own
+        let (chunk, remainder) = if chunk.is_empty() {
+            split_at_width(rest, first_char_width(rest))
+        } else {
+            (chunk, remainder)
+        };
+
+        if modes.insert {
+            insert_cells(surface, y, x, chunk.width(), &state.blank_cell());
+        }
+        seq = surface.add_change(chunk);
+        rest = remainder;
+    }
+
+    seq
+}
+
+fn first_char_width(text: &str) -> usize {
+    text.chars().next().and_then(|c| c.width()).unwrap_or(0)
 }
 
-pub fn process_print_string(surface: &mut Surface, str: &str) -> SequenceNo {
-    surface.add_change(str)
+/// Splits `text` after the longest prefix that fits in `max_width` columns,
+/// keeping zero-width characters attached to the preceding one.
+fn split_at_width(text: &str, max_width: usize) -> (&str, &str) {
+    let mut used = 0;
+    for (idx, ch) in text.char_indices() {
+        used += ch.width().unwrap_or(0);
+        if used > max_width {
+            return text.split_at(idx);
+        }
+    }
+    (text, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(surface: &Surface) -> Vec<String> {
+        surface
+            .screen_lines()
+            .iter()
+            .map(|line| line.visible_cells().map(|c| c.str().to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn split_at_width_keeps_combining_marks() {
+        assert_eq!(split_at_width("abc", 2), ("ab", "c"));
+        assert_eq!(split_at_width("e\u{301}x", 1), ("e\u{301}", "x"));
+        assert_eq!(split_at_width("漢字", 3), ("漢", "字"));
+        assert_eq!(split_at_width("ab", 5), ("ab", ""));
+    }
+
+    #[test]
+    fn print_wraps_at_right_margin() {
+        let mut s = Surface::new(3, 2);
+        process_print_string(&mut s, &mut TerminalState::default(), "abcde");
+        assert_eq!(rows(&s), ["abc", "de "]);
+        assert_eq!(s.cursor_position(), (2, 1));
+    }
+
+    #[test]
+    fn print_wrap_scrolls_only_the_region() {
+        let mut s = Surface::new(3, 3);
+        let mut state = TerminalState::default();
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(2),
+        });
+        s.add_change("zzz");
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(0),
+        });
+        state.set_scroll_region(&s, 0, 2);
+
+        process_print_string(&mut s, &mut state, "abcdefghi");
+
+        assert_eq!(rows(&s), ["def", "ghi", "zzz"]);
+    }
+
+    #[test]
+    fn print_wide_char_wraps_before_last_column() {
+        let mut s = Surface::new(3, 2);
+        process_print_string(&mut s, &mut TerminalState::default(), "ab漢");
+        assert_eq!(rows(&s)[1], "漢 ");
+    }
+
+    #[test]
+    fn print_single_char() {
+        let mut s = Surface::new(3, 1);
+        process_print(&mut s, &mut TerminalState::default(), 'x');
+        assert_eq!(rows(&s), ["x  "]);
+    }
+
+    #[test]
+    fn print_without_autowrap_overwrites_last_column() {
+        let mut s = Surface::new(4, 2);
+        let mut state = TerminalState::default();
+        state.modes_mut().autowrap = false;
+
+        process_print_string(&mut s, &mut state, "abcdefg");
+
+        assert_eq!(rows(&s), ["abcg", "    "]);
+    }
+
+    #[test]
+    fn print_in_insert_mode_shifts_line() {
+        let mut s = Surface::new(6, 1);
+        let mut state = TerminalState::default();
+        process_print_string(&mut s, &mut state, "abcd");
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(1),
+            y: Position::Absolute(0),
+        });
+        state.modes_mut().insert = true;
+
+        process_print_string(&mut s, &mut state, "XYZ");
+
+        assert_eq!(rows(&s), ["aXYZbc"]);
+    }
 }
@@ -0,0 +1,89 @@
+/// Character set that can be designated into G0 or G1
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CharacterSet {
+    #[default]
+    Ascii,
+    /// United Kingdom set, where `#` is the pound sign
+    Uk,
+    /// DEC Special Graphics, used to draw lines and boxes
+    DecLineDrawing,
+}
+
+impl CharacterSet {
+    /// Maps `ch` to the Unicode character it stands for in this set.
+    pub fn translate(self, ch: char) -> char {
+        match self {
+            Self::Ascii => ch,
+            Self::Uk => match ch {
+                '#' => '£',
+                _ => ch,
+            },
+            Self::DecLineDrawing => dec_line_drawing(ch),
+        }
+    }
+}
+
+fn dec_line_drawing(ch: char) -> char {
+    match ch {
+        '_' => ' ',
+        '`' => '◆',
+        'a' => '▒',
+        'b' => '␉',
+        'c' => '␌',
+        'd' => '␍',
+        'e' => '␊',
+        'f' => '°',
+        'g' => '±',
+        'h' => '␤',
+        'i' => '␋',
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'o' => '⎺',
+        'p' => '⎻',
+        'q' => '─',
+        'r' => '⎼',
+        's' => '⎽',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        'y' => '≤',
+        'z' => '≥',
+        '{' => 'π',
+        '|' => '≠',
+        '}' => '£',
+        '~' => '·',
+        _ => ch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_is_unchanged() {
+        assert_eq!(CharacterSet::Ascii.translate('q'), 'q');
+        assert_eq!(CharacterSet::Ascii.translate('#'), '#');
+    }
+
+    #[test]
+    fn test_uk_pound_sign() {
+        assert_eq!(CharacterSet::Uk.translate('#'), '£');
+        assert_eq!(CharacterSet::Uk.translate('a'), 'a');
+    }
+
+    #[test]
+    fn test_dec_line_drawing_box() {
+        let drawn: String = "lqkxmj"
+            .chars()
+            .map(|c| CharacterSet::DecLineDrawing.translate(c))
+            .collect();
+        assert_eq!(drawn, "┌─┐│└┘");
+        assert_eq!(CharacterSet::DecLineDrawing.translate('A'), 'A');
+    }
+}
@@ -0,0 +1,124 @@
+use std::{ops::Range, str::FromStr};
+
+/// Command of a session whose output is captured, found through the OSC 133
+/// marks written by shells with shell integration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CommandOutput {
+    /// Every line, prompts and commands included
+    #[default]
+    All,
+    /// Only the output of the last command
+    Last,
+    /// Only the output of the `n`th command, numbered from 1
+    Nth(usize),
+}
+
+impl FromStr for CommandOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(Self::All);
+        }
+        if s.eq_ignore_ascii_case("last") {
+            return Ok(Self::Last);
+        }
+
+        match s.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Self::Nth(n)),
+            _ => Err(format!(
+                "Invalid command output: {s}. Must be 'all', 'last' or a command number starting at 1"
+            )),
+        }
+    }
+}
+
+impl CommandOutput {
+    /// Lines of the selected output within `selection`, given the output
+    /// lines of each command, or `None` when the selection holds no such
+    /// command. Commands are numbered among those shown in `selection`.
+    pub fn lines(
+        &self,
+        commands: &[Range<usize>],
+        selection: Range<usize>,
+    ) -> Option<Range<usize>> {
+        let commands: Vec<Range<usize>> = commands
+            .iter()
+            .filter(|command| {
+                if command.is_empty() {
+                    selection.contains(&command.start)
+                } else {
+                    command.start < selection.end && command.end > selection.start
+                }
+            })
+            .map(|command| command.start.max(selection.start)..command.end.min(selection.end))
+            .collect();
+        match self {
+            Self::All => Some(selection),
+            Self::Last => commands.last().cloned(),
+            Self::Nth(n) => commands.get(n.checked_sub(1)?).cloned(),
+        }
+    }
+}
+
+/// Lines of a command run in a session with shell integration, counted from
+/// the first line of the primary screen, scrolled off lines included
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommandMarks {
+    /// First line of the output (OSC 133 C), `None` until the command runs
+    pub output: Option<usize>,
+    /// Line after the output (OSC 133 D or the next prompt), `None` while
+    /// the command runs
+    pub end: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output of `ls` on lines 2-3, `cd` printing nothing, then `pwd` on line 6
+    fn session() -> Vec<Range<usize>> {
+        vec![2..4, 5..5, 6..7]
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(CommandOutput::from_str("all"), Ok(CommandOutput::All));
+        assert_eq!(CommandOutput::from_str("LAST"), Ok(CommandOutput::Last));
+        assert_eq!(CommandOutput::from_str("2"), Ok(CommandOutput::Nth(2)));
+    }
+
+    #[test]
+    fn test_from_str_invalid() {
+        for input in ["0", "first", "-1"] {
+            let err = CommandOutput::from_str(input).unwrap_err();
+            assert!(err.contains("Invalid command output"), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_lines_of_commands() {
+        let commands = session();
+        assert_eq!(CommandOutput::All.lines(&commands, 0..8), Some(0..8));
+        assert_eq!(CommandOutput::Nth(1).lines(&commands, 0..8), Some(2..4));
+        assert_eq!(CommandOutput::Nth(2).lines(&commands, 0..8), Some(5..5));
+        assert_eq!(CommandOutput::Nth(3).lines(&commands, 0..8), Some(6..7));
+        assert_eq!(CommandOutput::Last.lines(&commands, 0..8), Some(6..7));
+        assert_eq!(CommandOutput::Nth(4).lines(&commands, 0..8), None);
+    }
+
+    #[test]
+    fn test_lines_within_selection() {
+        let commands = session();
+        assert_eq!(CommandOutput::Nth(1).lines(&commands, 3..8), Some(3..4));
+        assert_eq!(CommandOutput::Nth(2).lines(&commands, 3..8), Some(5..5));
+        assert_eq!(CommandOutput::Nth(1).lines(&commands, 5..8), Some(5..5));
+        let running = vec![1..2, 2..usize::MAX];
+        assert_eq!(CommandOutput::Last.lines(&running, 0..5), Some(2..5));
+    }
+
+    #[test]
+    fn test_lines_without_marks() {
+        assert_eq!(CommandOutput::Last.lines(&[], 0..1), None);
+    }
+}
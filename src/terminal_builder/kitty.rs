@@ -0,0 +1,357 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+};
+
+use flate2::read::ZlibDecoder;
+use image::{DynamicImage, ImageFormat, RgbImage, RgbaImage};
+use termwiz::escape::apc::{
+    KittyImageCompression, KittyImageData, KittyImageDelete, KittyImageFormat, KittyImagePlacement,
+    KittyImageTransmit, KittyImageVerbosity,
+};
+use thiserror::Error;
+
+/// Errors reported back to the program, formatted as kitty error replies
+#[derive(Debug, Error)]
+pub enum KittyError {
+    #[error("EINVAL:only direct transmission is supported")]
+    UnsupportedMedium,
+
+    #[error("EINVAL:{0}")]
+    InvalidData(String),
+
+    #[error("ENOENT:image not found")]
+    NotFound,
+
+    #[error("EBADF:{0}")]
+    Io(#[from] io::Error),
+}
+
+/// A transmission whose chunks are all received
+#[derive(Debug)]
+pub struct Transmission {
+    pub transmit: KittyImageTransmit,
+    /// Where to display the image once decoded, for `a=T`
+    pub placement: Option<KittyImagePlacement>,
+    pub verbosity: KittyImageVerbosity,
+    pub data: Vec<u8>,
+}
+
+/// Images transmitted with the kitty graphics protocol, kept until the
+/// program displays or deletes them.
+#[derive(Debug, Default)]
+pub struct KittyImages {
+    images: HashMap<u32, RgbaImage>,
+    /// Ids given to the images transmitted with a number (`I=`) instead of an id
+    numbers: HashMap<u32, u32>,
+    /// Last id given to a numbered image, counting down to stay clear of program ids
+    last_assigned_id: Option<u32>,
+    /// Transmission whose chunks (`m=1`) are still arriving
+    pending: Option<Transmission>,
+}
+
+impl KittyImages {
+    /// Adds a chunk to the current transmission. Returns the transmission once
+    /// its last chunk arrives; later chunks only carry their data, so the keys
+    /// of the first one are kept.
+    pub fn receive(
+        &mut self,
+        transmit: &KittyImageTransmit,
+        placement: Option<&KittyImagePlacement>,
+        verbosity: KittyImageVerbosity,
+    ) -> Result<Option<Transmission>, KittyError> {
+        let chunk = load_direct(&transmit.data);
+        let mut transmission = match self.pending.take() {
+            Some(pending) => pending,
+            None => Transmission {
+                transmit: transmit.clone(),
+                placement: placement.cloned(),
+                verbosity,
+                data: Vec::new(),
+            },
+        };
+        transmission.data.extend(chunk?);
+
+        if transmit.more_data_follows {
+            self.pending = Some(transmission);
+            Ok(None)
+        } else {
+            Ok(Some(transmission))
+        }
+    }
+
+    /// Keeps `image` for later display, returning the id it is stored under,
+    /// or `None` when the transmission named no image.
+    pub fn store(&mut self, transmit: &KittyImageTransmit, image: RgbaImage) -> Option<u32> {
+        let id = match (transmit.image_id, transmit.image_number) {
+            (Some(id), _) => id,
+            (None, Some(number)) => {
+                let id = self.last_assigned_id.map_or(u32::MAX, |id| id - 1);
+                self.last_assigned_id = Some(id);
+                self.numbers.insert(number, id);
+                id
+            }
+            (None, None) => return None,
+        };
+        self.images.insert(id, image);
+        Some(id)
+    }
+
+    /// Image stored under `image_id`, or under the id given to `image_number`.
+    pub fn get(
+        &self,
+        image_id: Option<u32>,
+        image_number: Option<u32>,
+    ) -> Result<&RgbaImage, KittyError> {
+        image_id
+            .or_else(|| image_number.and_then(|number| self.numbers.get(&number).copied()))
+            .and_then(|id| self.images.get(&id))
+            .ok_or(KittyError::NotFound)
+    }
+
+    /// Frees the stored images targeted by an uppercase delete. Placements
+    /// already drawn stay on the screen.
+    pub fn delete(&mut self, what: &KittyImageDelete) {
+        match *what {
+            KittyImageDelete::All { delete: true } => {
+                self.images.clear();
+                self.numbers.clear();
+            }
+            KittyImageDelete::ByImageId {
+                image_id,
+                delete: true,
+                ..
+            } => {
+                self.images.remove(&image_id);
+            }
+            KittyImageDelete::ByImageNumber {
+                image_number,
+                delete: true,
+                ..
+            } => {
+                if let Some(id) = self.numbers.remove(&image_number) {
+                    self.images.remove(&id);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Reads the payload of a chunk. Only data sent within the escape sequence is
+/// accepted: files and shared memory would be read from the capturing machine.
+fn load_direct(data: &KittyImageData) -> Result<Vec<u8>, KittyError> {
+    match data {
+        KittyImageData::Direct(_) | KittyImageData::DirectBin(_) => Ok(data.clone().load_data()?),
+        _ => Err(KittyError::UnsupportedMedium),
+    }
+}
+
+/// Decodes a complete transmission as PNG, or as raw RGB/RGBA pixels of the
+/// transmitted size.
+pub fn decode_kitty_image(transmission: &Transmission) -> Result<RgbaImage, KittyError> {
+    let transmit = &transmission.transmit;
+    let data = match transmit.compression {
+        KittyImageCompression::None => transmission.data.clone(),
+        KittyImageCompression::Deflate => {
+            let mut data = Vec::new();
+            ZlibDecoder::new(transmission.data.as_slice()).read_to_end(&mut data)?;
+            data
+        }
+    };
+
+    let size = || {
+        transmit
+            .width
+            .zip(transmit.height)
+            .ok_or_else(|| KittyError::InvalidData("missing image size".to_string()))
+    };
+    let invalid_size = || KittyError::InvalidData("data does not match image size".to_string());
+
+    match transmit.format.as_ref().unwrap_or(&KittyImageFormat::Rgba) {
+        KittyImageFormat::Png => image::load_from_memory_with_format(&data, ImageFormat::Png)
+            .map(|image| image.to_rgba8())
+            .map_err(|err| KittyError::InvalidData(err.to_string())),
+        KittyImageFormat::Rgba => {
+            let (width, height) = size()?;
+            RgbaImage::from_raw(width, height, data).ok_or_else(invalid_size)
+        }
+        KittyImageFormat::Rgb => {
+            let (width, height) = size()?;
+            RgbImage::from_raw(width, height, data)
+                .map(|image| DynamicImage::ImageRgb8(image).to_rgba8())
+                .ok_or_else(invalid_size)
+        }
+    }
+}
+
+/// Reply to a kitty graphics command, `None` when the program did not ask
+/// for one: commands without an id, and results silenced by `q=`.
+pub fn kitty_reply(
+    image_id: Option<u32>,
+    image_number: Option<u32>,
+    verbosity: KittyImageVerbosity,
+    result: &Result<(), KittyError>,
+) -> Option<String> {
+    if image_id.is_none() && image_number.is_none() {
+        return None;
+    }
+    let message = match (result, verbosity) {
+        (_, KittyImageVerbosity::Quiet) | (Ok(()), KittyImageVerbosity::OnlyErrors) => {
+            return None;
+        }
+        (Ok(()), KittyImageVerbosity::Verbose) => "OK".to_string(),
+        (Err(err), _) => err.to_string(),
+    };
+
+    let mut keys = Vec::new();
+    if let Some(id) = image_id {
+        keys.push(format!("i={id}"));
+    }
+    if let Some(number) = image_number {
+        keys.push(format!("I={number}"));
+    }
+    Some(format!("\x1b_G{};{message}\x1b\\", keys.join(",")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use flate2::{Compression, write::ZlibEncoder};
+    use image::Rgba;
+
+    fn transmit(
+        format: KittyImageFormat,
+        data: &[u8],
+        more_data_follows: bool,
+    ) -> KittyImageTransmit {
+        KittyImageTransmit {
+            format: Some(format),
+            data: KittyImageData::DirectBin(data.to_vec()),
+            width: Some(1),
+            height: Some(2),
+            image_id: Some(7),
+            image_number: None,
+            compression: KittyImageCompression::None,
+            more_data_follows,
+        }
+    }
+
+    fn decode(transmit: KittyImageTransmit) -> Result<RgbaImage, KittyError> {
+        let mut images = KittyImages::default();
+        let transmission = images
+            .receive(&transmit, None, KittyImageVerbosity::Verbose)?
+            .expect("transmission should be complete");
+        decode_kitty_image(&transmission)
+    }
+
+    #[test]
+    fn test_decode_rgb() {
+        let image = decode(transmit(KittyImageFormat::Rgb, &[1, 2, 3, 4, 5, 6], false))
+            .expect("image should decode");
+        assert_eq!(*image.get_pixel(0, 1), Rgba([4, 5, 6, 255]));
+    }
+
+    #[test]
+    fn test_decode_deflate_rgba() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[1, 2, 3, 4, 5, 6, 7, 8]).expect("write");
+        let mut transmit = transmit(
+            KittyImageFormat::Rgba,
+            &encoder.finish().expect("finish"),
+            false,
+        );
+        transmit.compression = KittyImageCompression::Deflate;
+
+        let image = decode(transmit).expect("image should decode");
+        assert_eq!(*image.get_pixel(0, 1), Rgba([5, 6, 7, 8]));
+    }
+
+    #[test]
+    fn test_decode_png() {
+        let mut png = Vec::new();
+        RgbaImage::from_pixel(3, 2, Rgba([9, 8, 7, 255]))
+            .write_to(&mut io::Cursor::new(&mut png), ImageFormat::Png)
+            .expect("encode");
+        let image = decode(transmit(KittyImageFormat::Png, &png, false)).expect("image");
+        assert_eq!(image.dimensions(), (3, 2));
+    }
+
+    #[test]
+    fn test_wrong_size_is_invalid() {
+        let result = decode(transmit(KittyImageFormat::Rgb, &[1, 2, 3], false));
+        assert!(matches!(result, Err(KittyError::InvalidData(_))));
+    }
+
+    #[test]
+    fn test_chunks_keep_first_keys() {
+        let mut images = KittyImages::default();
+        let first = transmit(KittyImageFormat::Rgb, &[1, 2, 3], true);
+        let mut last = transmit(KittyImageFormat::Rgba, &[4, 5, 6], false);
+        last.image_id = None;
+
+        assert!(
+            images
+                .receive(&first, None, KittyImageVerbosity::Verbose)
+                .expect("chunk")
+                .is_none()
+        );
+        let transmission = images
+            .receive(&last, None, KittyImageVerbosity::Verbose)
+            .expect("chunk")
+            .expect("transmission should be complete");
+        assert_eq!(transmission.transmit.image_id, Some(7));
+        assert!(decode_kitty_image(&transmission).is_ok());
+    }
+
+    #[test]
+    fn test_store_by_number_and_delete() {
+        let mut images = KittyImages::default();
+        let mut transmit = transmit(KittyImageFormat::Rgb, &[], false);
+        transmit.image_id = None;
+        transmit.image_number = Some(3);
+
+        let id = images.store(&transmit, RgbaImage::new(1, 1));
+        assert_eq!(id, Some(u32::MAX));
+        assert!(images.get(None, Some(3)).is_ok());
+
+        images.delete(&KittyImageDelete::ByImageNumber {
+            image_number: 3,
+            placement_id: None,
+            delete: true,
+        });
+        assert!(matches!(
+            images.get(None, Some(3)),
+            Err(KittyError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_replies() {
+        let ok = Ok(());
+        assert_eq!(
+            kitty_reply(Some(1), None, KittyImageVerbosity::Verbose, &ok).as_deref(),
+            Some("\x1b_Gi=1;OK\x1b\\")
+        );
+        assert_eq!(
+            kitty_reply(Some(1), None, KittyImageVerbosity::OnlyErrors, &ok),
+            None
+        );
+        assert_eq!(
+            kitty_reply(None, None, KittyImageVerbosity::Verbose, &ok),
+            None
+        );
+        assert_eq!(
+            kitty_reply(
+                None,
+                Some(2),
+                KittyImageVerbosity::OnlyErrors,
+                &Err(KittyError::NotFound)
+            )
+            .as_deref(),
+            Some("\x1b_GI=2;ENOENT:image not found\x1b\\")
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use std::{ops::Range, str::FromStr};
+
+/// Lines of the terminal history to capture
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LineSelection {
+    /// Only the lines left on the screen when the command ends
+    #[default]
+    Screen,
+    /// The whole history: the scrollback followed by the screen
+    All,
+    /// The last `n` lines of the history
+    Last(usize),
+    /// Lines `first` to `last` of the history, numbered from 1 and inclusive
+    Range {
+        /// First line to capture
+        first: usize,
+        /// Last line to capture
+        last: usize,
+    },
+}
+
+impl FromStr for LineSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("screen") {
+            return Ok(Self::Screen);
+        }
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(Self::All);
+        }
+
+        let invalid = || {
+            format!(
+                "Invalid line selection: {s}. Must be 'screen', 'all', a line count or a range like '10-20'"
+            )
+        };
+
+        if let Some((first, last)) = s.split_once('-') {
+            let first = first.trim().parse::<usize>().map_err(|_| invalid())?;
+            let last = last.trim().parse::<usize>().map_err(|_| invalid())?;
+            if first == 0 || first > last {
+                return Err(invalid());
+            }
+            return Ok(Self::Range { first, last });
+        }
+
+        s.parse::<usize>().map(Self::Last).map_err(|_| invalid())
+    }
+}
+
+impl LineSelection {
+    /// Indices of the selected lines in a history of `total` lines whose
+    /// last `screen` lines are the screen.
+    pub fn lines(&self, total: usize, screen: usize) -> Range<usize> {
+        match self {
+            Self::Screen => total.saturating_sub(screen)..total,
+            Self::All => 0..total,
+            Self::Last(n) => total.saturating_sub(*n)..total,
+            Self::Range { first, last } => {
+                let end = (*last).min(total);
+                first.saturating_sub(1).min(end)..end
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str_keywords() {
+        assert_eq!(LineSelection::from_str("screen"), Ok(LineSelection::Screen));
+        assert_eq!(LineSelection::from_str("ALL"), Ok(LineSelection::All));
+    }
+
+    #[test]
+    fn test_from_str_last_lines() {
+        assert_eq!(LineSelection::from_str("50"), Ok(LineSelection::Last(50)));
+    }
+
+    #[test]
+    fn test_from_str_range() {
+        assert_eq!(
+            LineSelection::from_str("10-20"),
+            Ok(LineSelection::Range {
+                first: 10,
+                last: 20
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_str_invalid() {
+        for input in ["abc", "0-5", "20-10", "5-", "-5"] {
+            let err = LineSelection::from_str(input).unwrap_err();
+            assert!(err.contains("Invalid line selection"), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_lines() {
+        assert_eq!(LineSelection::Screen.lines(10, 4), 6..10);
+        assert_eq!(LineSelection::All.lines(10, 4), 0..10);
+        assert_eq!(LineSelection::Last(3).lines(10, 4), 7..10);
+        assert_eq!(LineSelection::Last(30).lines(10, 4), 0..10);
+        assert_eq!(
+            LineSelection::Range { first: 2, last: 5 }.lines(10, 4),
+            1..5
+        );
+        assert_eq!(
+            LineSelection::Range {
+                first: 20,
+                last: 30
+            }
+            .lines(10, 4),
+            10..10
+        );
+    }
+}
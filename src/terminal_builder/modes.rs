@@ -0,0 +1,26 @@
+/// Terminal modes set and reset by SM/RM and DECSET/DECRST
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TerminalModes {
+    /// DECAWM: printing past the right margin wraps to the next line
+    pub autowrap: bool,
+    /// IRM: printed characters shift the rest of the line right
+    pub insert: bool,
+    /// DECOM: cursor rows are relative to the scroll region
+    pub origin: bool,
+    /// DECTCEM: the cursor is shown
+    pub cursor_visible: bool,
+    /// DECCKM: cursor keys send SS3 instead of CSI sequences
+    pub application_cursor_keys: bool,
+}
+
+impl Default for TerminalModes {
+    fn default() -> Self {
+        Self {
+            autowrap: true,
+            insert: false,
+            origin: false,
+            cursor_visible: true,
+            application_cursor_keys: false,
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use image::{Rgba, RgbaImage};
+use termwiz::escape::{Sixel, SixelData};
+
+/// Number of color registers a sixel image can define
+const COLOR_REGISTERS: usize = 1024;
+
+/// Colors of the first 16 registers before the image defines them, as on a VT340
+const VT340_COLORS: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [51, 51, 204],
+    [204, 36, 36],
+    [51, 204, 51],
+    [204, 51, 204],
+    [51, 204, 204],
+    [204, 204, 51],
+    [120, 120, 120],
+    [69, 69, 69],
+    [87, 87, 153],
+    [153, 69, 69],
+    [87, 153, 87],
+    [153, 87, 153],
+    [87, 153, 153],
+    [153, 153, 87],
+    [204, 204, 204],
+];
+
+/// Decodes a sixel image, cropped to `max_size` pixels. Pixels the image does
+/// not draw are transparent when the image asks for it, and `background`
+/// otherwise. Returns `None` for an empty image.
+pub fn decode_sixel(
+    sixel: &Sixel,
+    background: Rgba<u8>,
+    (max_width, max_height): (u32, u32),
+) -> Option<RgbaImage> {
+    let (width, height) = sixel.dimensions();
+    let (width, height) = (width.min(max_width), height.min(max_height));
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let fill = if sixel.background_is_transparent {
+        Rgba([0, 0, 0, 0])
+    } else {
+        background
+    };
+    let mut image = RgbaImage::from_pixel(width, height, fill);
+
+    let mut registers: Vec<Rgba<u8>> = (0..COLOR_REGISTERS)
+        .map(|idx| {
+            let [r, g, b] = VT340_COLORS.get(idx).copied().unwrap_or_default();
+            Rgba([r, g, b, 0xff])
+        })
+        .collect();
+    let mut color = registers[0];
+    let (mut x, mut y) = (0u32, 0u32);
+
+    let draw = |image: &mut RgbaImage, x: u32, y: u32, value: u8, color: Rgba<u8>| {
+        for bit in 0..6 {
+            if value & (1 << bit) != 0 && x < width && y + bit < height {
+                image.put_pixel(x, y + bit, color);
+            }
+        }
+    };
+
+    for data in &sixel.data {
+        match data {
+            SixelData::Data(value) => {
+                draw(&mut image, x, y, *value, color);
+                x = x.saturating_add(1);
+            }
+            SixelData::Repeat { repeat_count, data } => {
+                // Pixels past the right edge are cropped
+                for _ in 0..(*repeat_count).min(width.saturating_sub(x)) {
+                    draw(&mut image, x, y, *data, color);
+                    x = x.saturating_add(1);
+                }
+            }
+            SixelData::DefineColorMapRGB { color_number, rgb } => {
+                if let Some(register) = registers.get_mut(usize::from(*color_number)) {
+                    let (r, g, b) = rgb.to_tuple_rgb8();
+                    *register = Rgba([r, g, b, 0xff]);
+                    color = *register;
+                }
+            }
+            SixelData::DefineColorMapHSL {
+                color_number,
+                hue_angle,
+                lightness,
+                saturation,
+            } => {
+                if let Some(register) = registers.get_mut(usize::from(*color_number)) {
+                    *register = hls_to_rgba(*hue_angle, *lightness, *saturation);
+                    color = *register;
+                }
+            }
+            SixelData::SelectColorMapEntry(color_number) => {
+                if let Some(register) = registers.get(usize::from(*color_number)) {
+                    color = *register;
+                }
+            }
+            SixelData::CarriageReturn => x = 0,
+            SixelData::NewLine => {
+                x = 0;
+                y = y.saturating_add(6);
+            }
+        }
+    }
+
+    Some(image)
+}
+
+fn to_u8(component: f32) -> u8 {
+    (component * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Converts a sixel HLS color, where a hue of 0 degrees is blue, to RGB.
+fn hls_to_rgba(hue_angle: u16, lightness: u8, saturation: u8) -> Rgba<u8> {
+    let hue = f32::from((hue_angle % 360 + 240) % 360) / 60.0;
+    let lightness = f32::from(lightness.min(100)) / 100.0;
+    let saturation = f32::from(saturation.min(100)) / 100.0;
+
+    let chroma = (1.0 - 2.0f32.mul_add(lightness, -1.0).abs()) * saturation;
+    let second = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, second, 0.0),
+        1 => (second, chroma, 0.0),
+        2 => (0.0, chroma, second),
+        3 => (0.0, second, chroma),
+        4 => (second, 0.0, chroma),
+        _ => (chroma, 0.0, second),
+    };
+    let offset = lightness - chroma / 2.0;
+    Rgba([
+        to_u8(r + offset),
+        to_u8(g + offset),
+        to_u8(b + offset),
+        0xff,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use termwiz::color::RgbColor;
+
+    fn sixel(transparent: bool, data: Vec<SixelData>) -> Sixel {
+        Sixel {
+            pan: 1,
+            pad: 1,
+            pixel_width: None,
+            pixel_height: None,
+            background_is_transparent: transparent,
+            horizontal_grid_size: None,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_decode_columns_and_rows() {
+        let red = RgbColor::new_8bpc(255, 0, 0);
+        let image = decode_sixel(
+            &sixel(
+                true,
+                vec![
+                    SixelData::DefineColorMapRGB {
+                        color_number: 1,
+                        rgb: red,
+                    },
+                    SixelData::Repeat {
+                        repeat_count: 2,
+                        data: 0b000001,
+                    },
+                    SixelData::NewLine,
+                    SixelData::Data(0b100000),
+                ],
+            ),
+            Rgba([0, 0, 0, 0xff]),
+            (100, 100),
+        )
+        .expect("image should not be empty");
+
+        assert_eq!(image.dimensions(), (2, 12));
+        assert_eq!(*image.get_pixel(0, 0), Rgba([255, 0, 0, 0xff]));
+        assert_eq!(*image.get_pixel(1, 0), Rgba([255, 0, 0, 0xff]));
+        assert_eq!(*image.get_pixel(0, 11), Rgba([255, 0, 0, 0xff]));
+        assert_eq!(image.get_pixel(0, 1)[3], 0);
+    }
+
+    #[test]
+    fn test_opaque_background() {
+        let background = Rgba([1, 2, 3, 0xff]);
+        let image = decode_sixel(
+            &sixel(false, vec![SixelData::Data(1)]),
+            background,
+            (100, 100),
+        )
+        .expect("image should not be empty");
+        assert_eq!(*image.get_pixel(0, 1), background);
+    }
+
+    #[test]
+    fn test_oversized_image_is_cropped() {
+        let mut oversized = sixel(
+            true,
+            vec![
+                SixelData::Repeat {
+                    repeat_count: u32::MAX,
+                    data: 0b111111,
+                },
+                SixelData::NewLine,
+                SixelData::Data(1),
+            ],
+        );
+        oversized.pixel_width = Some(u32::MAX);
+        oversized.pixel_height = Some(u32::MAX);
+
+        let image = decode_sixel(&oversized, Rgba([0, 0, 0, 0xff]), (40, 8))
+            .expect("image should not be empty");
+        assert_eq!(image.dimensions(), (40, 8));
+        assert_eq!(image.get_pixel(39, 5)[3], 0xff);
+        assert_eq!(image.get_pixel(0, 6)[3], 0xff);
+    }
+
+    #[test]
+    fn test_empty_image() {
+        assert!(decode_sixel(&sixel(true, vec![]), Rgba([0, 0, 0, 0xff]), (100, 100)).is_none());
+    }
+
+    #[test]
+    fn test_hls_colors() {
+        assert_eq!(hls_to_rgba(0, 50, 100), Rgba([0, 0, 255, 0xff]));
+        assert_eq!(hls_to_rgba(120, 50, 100), Rgba([255, 0, 0, 0xff]));
+        assert_eq!(hls_to_rgba(240, 50, 100), Rgba([0, 255, 0, 0xff]));
+        assert_eq!(hls_to_rgba(0, 100, 0), Rgba([255, 255, 255, 0xff]));
+    }
+}
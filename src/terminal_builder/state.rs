@@ -0,0 +1,807 @@
+use std::{borrow::Cow, collections::VecDeque, mem, ops::Range};
+
+use image::Rgba;
+use termwiz::{
+    cell::{AttributeChange, Cell, CellAttributes, SemanticType},
+    escape::osc::Progress,
+    surface::{Change, CursorShape, Line, Position, SEQ_ZERO, SequenceNo, Surface},
+};
+use tracing::warn;
+
+use crate::constants::SCROLLBACK_MAX_LINES;
+use crate::terminal_builder::{
+    ScreenBuffer, charset::CharacterSet, command_output::CommandMarks, kitty::KittyImages,
+    modes::TerminalModes, tab_stops::TabStops, task_progress::TaskProgress, utils::fill_cells,
+};
+use crate::theme::Theme;
+
+/// Cursor state saved by DECSC and restored by DECRC
+#[derive(Clone, Default)]
+struct SavedCursor {
+    position: (usize, usize),
+    pen: CellAttributes,
+    charsets: [CharacterSet; 2],
+    shifted_out: bool,
+    origin: bool,
+}
+
+/// Per-capture terminal state that the termwiz `Surface` does not expose.
+#[derive(Default)]
+pub struct TerminalState {
+    /// Attributes applied to newly printed cells
+    pen: CellAttributes,
+    /// Rows set by DECSTBM, `None` when scrolling affects the whole screen
+    scroll_region: Option<Range<usize>>,
+    /// Buffer currently displayed on the active surface
+    active_buffer: ScreenBuffer,
+    /// The buffer that is not displayed, parked until the program switches back
+    inactive_screen: Option<Surface>,
+    /// Last frame of the alternate screen, kept after the program leaves it
+    last_alternate_frame: Option<Surface>,
+    /// Cursor saved when entering the alternate screen with mode 1049
+    alternate_saved_cursor: Option<(usize, usize)>,
+    /// Lines scrolled off the top of the primary screen, oldest first
+    scrollback: VecDeque<Line>,
+    /// Number of lines discarded because the scrollback was full
+    dropped_lines: usize,
+    /// Number of lines discarded by ED 3
+    cleared_lines: usize,
+    /// Commands marked with OSC 133 on the primary screen, oldest first
+    commands: Vec<CommandMarks>,
+    /// Character sets designated into G0 and G1
+    charsets: [CharacterSet; 2],
+    /// Whether G1 is invoked by SO instead of G0
+    shifted_out: bool,
+    /// Cursors saved by DECSC or CSI s, most recent last
+    saved_cursors: Vec<SavedCursor>,
+    /// Columns used by horizontal tab
+    tab_stops: TabStops,
+    /// Modes set by SM/RM and DECSET/DECRST
+    modes: TerminalModes,
+    /// Last printed character with its combining marks, repeated by REP
+    last_printed: Option<String>,
+    /// Window title set by OSC 0 or OSC 2
+    title: Option<String>,
+    /// Colors of this capture, as changed by OSC 4, 10 and 11
+    theme: Theme,
+    /// Colors the capture started with, restored by OSC 104, 110, 111 and RIS
+    initial_theme: Theme,
+    /// Images transmitted with the kitty graphics protocol
+    kitty_images: KittyImages,
+    /// Cursor shape set by DECSCUSR
+    cursor_shape: CursorShape,
+    /// Progress reported with OSC 9;4
+    progress: TaskProgress,
+}
+
+impl TerminalState {
+    /// State of a terminal whose colors come from `theme`, with a tab stop
+    /// every `tab_width` columns.
+    pub fn new(theme: Theme, tab_width: usize) -> Self {
+        Self {
+            tab_stops: TabStops::new(tab_width),
+            theme: theme.clone(),
+            initial_theme: theme,
+            ..Self::default()
+        }
+    }
+
+    pub fn apply_attribute(
+        &mut self,
+        surface: &mut Surface,
+        change: AttributeChange,
+    ) -> SequenceNo {
+        self.pen.apply_change(&change);
+        surface.add_change(Change::Attribute(change))
+    }
+
+    pub fn pen(&self) -> &CellAttributes {
+        &self.pen
+    }
+
+    pub fn set_pen(&mut self, surface: &mut Surface, pen: CellAttributes) -> SequenceNo {
+        self.pen = pen;
+        surface.add_change(Change::AllAttributes(self.pen.clone()))
+    }
+
+    /// Resets the pen to default attributes (SGR 0), keeping the semantic
+    /// type that only OSC 133 changes and the hyperlink that only OSC 8 ends.
+    pub fn reset_pen(&mut self, surface: &mut Surface) -> SequenceNo {
+        let mut pen = CellAttributes::default();
+        pen.set_semantic_type(self.pen.semantic_type());
+        pen.set_hyperlink(self.pen.hyperlink().cloned());
+        self.set_pen(surface, pen)
+    }
+
+    /// Tags the cells printed from now on as prompt, input or output (OSC 133).
+    pub fn set_semantic_type(
+        &mut self,
+        surface: &mut Surface,
+        semantic_type: SemanticType,
+    ) -> SequenceNo {
+        let mut pen = self.pen.clone();
+        pen.set_semantic_type(semantic_type);
+        self.set_pen(surface, pen)
+    }
+
+    /// Cell used when erasing: a space that only keeps the current background color.
+    pub fn blank_cell(&self) -> Cell {
+        let mut attrs = CellAttributes::blank();
+        attrs.set_background(self.pen.background());
+        Cell::new(' ', attrs)
+    }
+
+    /// Designates `charset` into G0 (`index` 0) or G1 (`index` 1).
+    pub fn designate_charset(&mut self, index: usize, charset: CharacterSet) {
+        if let Some(slot) = self.charsets.get_mut(index) {
+            *slot = charset;
+        }
+    }
+
+    /// Invokes G1 (SO) or G0 (SI) for the next printed characters.
+    pub fn shift_out(&mut self, shifted_out: bool) {
+        self.shifted_out = shifted_out;
+    }
+
+    /// Translates printed `text` through the character set in use.
+    pub fn translate<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let charset = self.charsets[usize::from(self.shifted_out)];
+        if charset == CharacterSet::Ascii {
+            return Cow::Borrowed(text);
+        }
+        Cow::Owned(text.chars().map(|ch| charset.translate(ch)).collect())
+    }
+
+    pub fn modes(&self) -> TerminalModes {
+        self.modes
+    }
+
+    pub fn modes_mut(&mut self) -> &mut TerminalModes {
+        &mut self.modes
+    }
+
+    /// Screen row for a zero-based cursor `row`, counted from the top of the
+    /// scroll region and kept inside it when origin mode is set.
+    pub fn origin_row(&self, surface: &Surface, row: usize) -> usize {
+        if self.modes.origin {
+            let region = self.scroll_region(surface);
+            (region.start + row).min(region.end.saturating_sub(1))
+        } else {
+            row
+        }
+    }
+
+    /// Screen row `lines` above or below the cursor. The move stops at the
+    /// margins of the scroll region when the cursor starts inside it, and at the
+    /// screen edges otherwise.
+    pub fn vertical_move_row(&self, surface: &Surface, lines: isize) -> usize {
+        let (_, y) = surface.cursor_position();
+        let region = self.scroll_region(surface);
+        let (top, bottom) = if region.contains(&y) {
+            (region.start, region.end - 1)
+        } else {
+            (0, surface.dimensions().1.saturating_sub(1))
+        };
+        y.saturating_add_signed(lines).clamp(top, bottom)
+    }
+
+    /// Moves the cursor to the home position, the top of the scroll region in origin mode.
+    pub fn home_cursor(&self, surface: &mut Surface) -> SequenceNo {
+        surface.add_change(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(self.origin_row(surface, 0)),
+        })
+    }
+
+    /// Saves the cursor position, attributes and character sets (DECSC).
+    pub fn save_cursor(&mut self, surface: &Surface) {
+        self.saved_cursors.push(SavedCursor {
+            position: surface.cursor_position(),
+            pen: self.pen.clone(),
+            charsets: self.charsets,
+            shifted_out: self.shifted_out,
+            origin: self.modes.origin,
+        });
+    }
+
+    /// Restores the most recently saved cursor, or homes the cursor with
+    /// default attributes when nothing was saved (DECRC). Nested saves are
+    /// popped, the outermost one can be restored any number of times.
+    pub fn restore_cursor(&mut self, surface: &mut Surface) -> SequenceNo {
+        let saved = if self.saved_cursors.len() > 1 {
+            self.saved_cursors.pop().unwrap_or_default()
+        } else {
+            self.saved_cursors.last().cloned().unwrap_or_default()
+        };
+        self.pen = saved.pen;
+        self.charsets = saved.charsets;
+        self.shifted_out = saved.shifted_out;
+        self.modes.origin = saved.origin;
+        self.restore_cursor_and_pen(surface, saved.position)
+    }
+
+    /// Sets a tab stop at the cursor column (HTS).
+    pub fn set_tab_stop(&mut self, surface: &Surface) {
+        self.tab_stops.set(surface.cursor_position().0);
+    }
+
+    /// Clears the tab stop at the cursor column (TBC 0).
+    pub fn clear_tab_stop(&mut self, surface: &Surface) {
+        self.tab_stops.clear(surface.cursor_position().0);
+    }
+
+    /// Clears every tab stop (TBC 3).
+    pub fn clear_tab_stops(&mut self) {
+        self.tab_stops.clear_all();
+    }
+
+    /// Moves the cursor forward `count` tab stops, stopping at the right margin (HT, CHT).
+    pub fn horizontal_tab(&mut self, surface: &mut Surface, count: usize) -> SequenceNo {
+        let (mut x, _) = surface.cursor_position();
+        let width = surface.dimensions().0;
+        // Each stop is at least a column further, so the margin is reached
+        // within `width` stops
+        for _ in 0..count.min(width) {
+            x = self.tab_stops.next(x, width);
+        }
+        surface.add_change(Change::CursorPosition {
+            x: Position::Absolute(x),
+            y: Position::Relative(0),
+        })
+    }
+
+    /// Moves the cursor back `count` tab stops, stopping at the left margin (CBT).
+    pub fn backward_tab(&mut self, surface: &mut Surface, count: usize) -> SequenceNo {
+        let (mut x, width) = (surface.cursor_position().0, surface.dimensions().0);
+        for _ in 0..count.min(width) {
+            x = self.tab_stops.previous(x);
+        }
+        surface.add_change(Change::CursorPosition {
+            x: Position::Absolute(x),
+            y: Position::Relative(0),
+        })
+    }
+
+    /// Resets the terminal to its initial state (RIS), back on a cleared
+    /// primary screen. The scrollback is kept.
+    pub fn reset(&mut self, surface: &mut Surface) -> SequenceNo {
+        self.leave_alternate_screen(surface, true, false);
+        *self = Self {
+            scrollback: mem::take(&mut self.scrollback),
+            dropped_lines: self.dropped_lines,
+            cleared_lines: self.cleared_lines,
+            commands: mem::take(&mut self.commands),
+            last_alternate_frame: self.last_alternate_frame.take(),
+            theme: self.initial_theme.clone(),
+            initial_theme: mem::take(&mut self.initial_theme),
+            tab_stops: mem::take(&mut self.tab_stops),
+            ..Self::default()
+        };
+        self.tab_stops.reset();
+
+        let height = surface.dimensions().1;
+        self.fill_rows(surface, 0..height);
+        self.restore_cursor_and_pen(surface, (0, 0))
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = Some(title.to_string());
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Changes the default text color (OSC 10).
+    pub fn set_foreground_color(&mut self, color: Rgba<u8>) {
+        self.theme.foreground_color = color;
+    }
+
+    /// Changes the default background color (OSC 11).
+    pub fn set_background_color(&mut self, color: Rgba<u8>) {
+        self.theme.background_color = color;
+    }
+
+    /// Restores the default text color to the theme color (OSC 110).
+    pub fn reset_foreground_color(&mut self) {
+        self.theme.foreground_color = self.initial_theme.foreground_color;
+    }
+
+    /// Restores the default background color to the theme color (OSC 111).
+    pub fn reset_background_color(&mut self) {
+        self.theme.background_color = self.initial_theme.background_color;
+    }
+
+    /// Redefines palette entry `index` (OSC 4).
+    pub fn set_palette_color(&mut self, index: u8, color: Rgba<u8>) {
+        self.theme.palette[usize::from(index)] = color;
+    }
+
+    /// Restores palette entry `index` to the theme color (OSC 104).
+    pub fn reset_palette_color(&mut self, index: u8) {
+        let index = usize::from(index);
+        self.theme.palette[index] = self.initial_theme.palette[index];
+    }
+
+    /// Restores every palette entry to the theme colors (OSC 104).
+    pub fn reset_palette(&mut self) {
+        self.theme.palette = self.initial_theme.palette;
+    }
+
+    pub fn cursor_shape(&self) -> CursorShape {
+        self.cursor_shape
+    }
+
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.cursor_shape = shape;
+    }
+
+    pub fn progress(&self) -> TaskProgress {
+        self.progress
+    }
+
+    pub fn report_progress(&mut self, report: &Progress) {
+        self.progress = self.progress.update(report);
+    }
+
+    pub fn kitty_images_mut(&mut self) -> &mut KittyImages {
+        &mut self.kitty_images
+    }
+
+    pub fn last_printed(&self) -> Option<String> {
+        self.last_printed.clone()
+    }
+
+    pub fn set_last_printed(&mut self, text: &str) {
+        self.last_printed = Some(text.to_string());
+    }
+
+    /// Rows that scroll on line feed and reverse index.
+    pub fn scroll_region(&self, surface: &Surface) -> Range<usize> {
+        let height = surface.dimensions().1;
+        self.scroll_region
+            .clone()
+            .filter(|region| region.end <= height)
+            .unwrap_or(0..height)
+    }
+
+    /// Sets the scroll region to `top..bottom`, ignoring regions smaller than two rows.
+    pub fn set_scroll_region(&mut self, surface: &Surface, top: usize, bottom: usize) -> bool {
+        let height = surface.dimensions().1;
+        let bottom = bottom.min(height);
+        if top + 1 >= bottom {
+            return false;
+        }
+
+        self.scroll_region = (top != 0 || bottom != height).then_some(top..bottom);
+        true
+    }
+
+    /// Scrolls `rows` up by `count`, filling the new bottom rows with blank cells.
+    pub fn scroll_up(&self, surface: &mut Surface, rows: Range<usize>, count: usize) -> SequenceNo {
+        let count = count.min(rows.len());
+        if count == 0 {
+            return SEQ_ZERO;
+        }
+
+        let seq = surface.add_change(Change::ScrollRegionUp {
+            first_row: rows.start,
+            region_size: rows.len(),
+            scroll_count: count,
+        });
+        self.fill_rows(surface, rows.end - count..rows.end);
+        seq
+    }
+
+    /// Scrolls `rows` down by `count`, filling the new top rows with blank cells.
+    pub fn scroll_down(
+        &self,
+        surface: &mut Surface,
+        rows: Range<usize>,
+        count: usize,
+    ) -> SequenceNo {
+        let count = count.min(rows.len());
+        if count == 0 {
+            return SEQ_ZERO;
+        }
+
+        let seq = surface.add_change(Change::ScrollRegionDown {
+            first_row: rows.start,
+            region_size: rows.len(),
+            scroll_count: count,
+        });
+        self.fill_rows(surface, rows.start..rows.start + count);
+        seq
+    }
+
+    /// Moves the cursor down one row, scrolling the region when it sits on its bottom margin.
+    pub fn line_feed(&mut self, surface: &mut Surface) -> SequenceNo {
+        let (_, y) = surface.cursor_position();
+        let region = self.scroll_region(surface);
+
+        if y + 1 == region.end {
+            if region.start == 0 && self.active_buffer == ScreenBuffer::Primary {
+                self.push_scrollback(surface);
+            }
+            self.scroll_up(surface, region, 1)
+        } else {
+            surface.add_change(Change::CursorPosition {
+                x: Position::Relative(0),
+                y: Position::Relative(1),
+            })
+        }
+    }
+
+    /// Moves the cursor up one row, scrolling the region when it sits on its top margin.
+    pub fn reverse_index(&self, surface: &mut Surface) -> SequenceNo {
+        let (_, y) = surface.cursor_position();
+        let region = self.scroll_region(surface);
+
+        if y == region.start {
+            self.scroll_down(surface, region, 1)
+        } else {
+            surface.add_change(Change::CursorPosition {
+                x: Position::Relative(0),
+                y: Position::Relative(-1),
+            })
+        }
+    }
+
+    /// Switches `surface` to the alternate screen, optionally saving the cursor
+    /// and clearing the alternate screen first (mode 1049).
+    pub fn enter_alternate_screen(&mut self, surface: &mut Surface, save_cursor_and_clear: bool) {
+        if self.active_buffer == ScreenBuffer::Alternate {
+            return;
+        }
+
+        let cursor = surface.cursor_position();
+        if save_cursor_and_clear {
+            self.alternate_saved_cursor = Some(cursor);
+        }
+
+        let (width, height) = surface.dimensions();
+        let alternate = self
+            .inactive_screen
+            .take()
+            .filter(|alternate| alternate.dimensions() == (width, height))
+            .unwrap_or_else(|| Surface::new(width, height));
+        self.inactive_screen = Some(mem::replace(surface, alternate));
+        self.active_buffer = ScreenBuffer::Alternate;
+
+        if save_cursor_and_clear {
+            self.fill_rows(surface, 0..height);
+        }
+        self.restore_cursor_and_pen(surface, cursor);
+    }
+
+    /// Switches `surface` back to the primary screen, optionally clearing the
+    /// alternate screen (mode 1047) or restoring the saved cursor (mode 1049).
+    pub fn leave_alternate_screen(
+        &mut self,
+        surface: &mut Surface,
+        clear: bool,
+        restore_cursor: bool,
+    ) {
+        if self.active_buffer == ScreenBuffer::Primary {
+            return;
+        }
+        let Some(primary) = self.inactive_screen.take() else {
+            return;
+        };
+
+        let mut cursor = surface.cursor_position();
+        let mut alternate = mem::replace(surface, primary);
+        self.last_alternate_frame = Some(alternate.clone());
+        if clear {
+            let height = alternate.dimensions().1;
+            self.fill_rows(&mut alternate, 0..height);
+        }
+        self.inactive_screen = Some(alternate);
+        self.active_buffer = ScreenBuffer::Primary;
+
+        if restore_cursor && let Some(saved) = self.alternate_saved_cursor.take() {
+            cursor = saved;
+        }
+        self.restore_cursor_and_pen(surface, cursor);
+    }
+
+    /// Returns the requested buffer once the output has been fully processed,
+    /// falling back to the active surface when it was never used.
+    pub fn take_screen(&mut self, surface: Surface, buffer: ScreenBuffer) -> Surface {
+        match (buffer, self.active_buffer) {
+            (ScreenBuffer::Primary, ScreenBuffer::Alternate) => {
+                self.inactive_screen.take().unwrap_or(surface)
+            }
+            (ScreenBuffer::Alternate, ScreenBuffer::Primary) => {
+                if let Some(frame) = self.last_alternate_frame.take() {
+                    frame
+                } else {
+                    warn!("The alternate screen was never used, capturing the primary screen");
+                    surface
+                }
+            }
+            (ScreenBuffer::Primary, ScreenBuffer::Primary)
+            | (ScreenBuffer::Alternate, ScreenBuffer::Alternate) => surface,
+        }
+    }
+
+    /// Discards the lines scrolled off the screen (ED 3).
+    pub fn clear_scrollback(&mut self) {
+        self.cleared_lines += self.scrollback.len();
+        self.scrollback.clear();
+    }
+
+    /// Records a prompt (OSC 133 A), which ends the command that was running.
+    pub fn mark_prompt(&mut self, surface: &Surface) {
+        if self.active_buffer == ScreenBuffer::Primary {
+            self.end_command(self.history_line(surface));
+            self.commands.push(CommandMarks::default());
+        }
+    }
+
+    /// Records the start of the output of the command typed at the last
+    /// prompt (OSC 133 C).
+    pub fn mark_command_output(&mut self, surface: &Surface) {
+        if self.active_buffer != ScreenBuffer::Primary {
+            return;
+        }
+        let line = self.history_line(surface);
+        match self.commands.last_mut() {
+            Some(command) if command.output.is_none() => command.output = Some(line),
+            _ => self.commands.push(CommandMarks {
+                output: Some(line),
+                end: None,
+            }),
+        }
+    }
+
+    /// Records the end of the output of the running command (OSC 133 D).
+    pub fn mark_command_end(&mut self, surface: &Surface) {
+        if self.active_buffer == ScreenBuffer::Primary {
+            // Output without a final line feed ends on the cursor line
+            let line = self.history_line(surface) + usize::from(surface.cursor_position().0 > 0);
+            self.end_command(line);
+        }
+    }
+
+    /// Output lines of each command that ran, as indices in the scrollback
+    /// followed by the screen. The output of a command still running reaches
+    /// `usize::MAX`.
+    pub fn command_lines(&self) -> Vec<Range<usize>> {
+        let discarded = self.dropped_lines + self.cleared_lines;
+        self.commands
+            .iter()
+            .filter_map(|command| {
+                let start = command.output?.saturating_sub(discarded);
+                let end = command
+                    .end
+                    .map_or(usize::MAX, |end| end.saturating_sub(discarded));
+                Some(start..end.max(start))
+            })
+            .collect()
+    }
+
+    fn end_command(&mut self, line: usize) {
+        if let Some(command) = self
+            .commands
+            .last_mut()
+            .filter(|command| command.output.is_some() && command.end.is_none())
+        {
+            command.end = Some(line);
+        }
+    }
+
+    /// Line of the cursor counted from the first line of the primary screen,
+    /// scrolled off lines included
+    fn history_line(&self, surface: &Surface) -> usize {
+        self.dropped_lines
+            + self.cleared_lines
+            + self.scrollback.len()
+            + surface.cursor_position().1
+    }
+
+    /// Returns the lines scrolled off the primary screen, warning when some
+    /// were dropped because the scrollback was full.
+    pub fn take_scrollback(&mut self) -> VecDeque<Line> {
+        if self.dropped_lines > 0 {
+            warn!(
+                "{} lines exceeded the scrollback limit of {SCROLLBACK_MAX_LINES} lines and were dropped",
+                self.dropped_lines
+            );
+        }
+        mem::take(&mut self.scrollback)
+    }
+
+    fn push_scrollback(&mut self, surface: &mut Surface) {
+        let Some(cells) = surface.screen_cells().into_iter().next() else {
+            return;
+        };
+        let mut line = Line::from_cells(cells.to_vec(), SEQ_ZERO);
+        line.prune_trailing_blanks(SEQ_ZERO);
+        line.compress_for_scrollback();
+
+        if self.scrollback.len() == SCROLLBACK_MAX_LINES {
+            self.scrollback.pop_front();
+            self.dropped_lines += 1;
+        }
+        self.scrollback.push_back(line);
+    }
+
+    fn restore_cursor_and_pen(&self, surface: &mut Surface, (x, y): (usize, usize)) -> SequenceNo {
+        surface.add_change(Change::AllAttributes(self.pen.clone()));
+        surface.add_change(Change::CursorPosition {
+            x: Position::Absolute(x),
+            y: Position::Absolute(y),
+        })
+    }
+
+    fn fill_rows(&self, surface: &mut Surface, rows: Range<usize>) {
+        let width = surface.dimensions().0;
+        let blank = self.blank_cell();
+        for row in rows {
+            fill_cells(surface, row, 0..width, &blank);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use termwiz::{cell::Intensity, color::ColorAttribute};
+
+    #[test]
+    fn apply_attribute_updates_surface() {
+        let mut s = Surface::new(5, 1);
+        let mut state = TerminalState::default();
+
+        state.apply_attribute(&mut s, AttributeChange::Intensity(Intensity::Bold));
+        s.add_change("A");
+
+        assert_eq!(s.screen_cells()[0][0].attrs().intensity(), Intensity::Bold);
+    }
+
+    #[test]
+    fn blank_cell_keeps_only_background() {
+        let mut state = TerminalState::default();
+        let mut s = Surface::new(5, 1);
+
+        state.apply_attribute(&mut s, AttributeChange::Intensity(Intensity::Bold));
+        state.apply_attribute(
+            &mut s,
+            AttributeChange::Background(ColorAttribute::PaletteIndex(4)),
+        );
+
+        let blank = state.blank_cell();
+        assert_eq!(blank.str(), " ");
+        assert_eq!(blank.attrs().intensity(), Intensity::Normal);
+        assert_eq!(blank.attrs().background(), ColorAttribute::PaletteIndex(4));
+    }
+
+    fn surface_with_rows(rows: &[&str]) -> Surface {
+        let mut s = Surface::new(3, rows.len());
+        for (y, text) in rows.iter().enumerate() {
+            s.add_change(Change::CursorPosition {
+                x: Position::Absolute(0),
+                y: Position::Absolute(y),
+            });
+            s.add_change(*text);
+        }
+        s
+    }
+
+    fn rows(surface: &Surface) -> Vec<String> {
+        surface
+            .screen_lines()
+            .iter()
+            .map(|line| line.visible_cells().map(|c| c.str().to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn set_scroll_region_rejects_single_row() {
+        let s = Surface::new(3, 5);
+        let mut state = TerminalState::default();
+
+        assert!(!state.set_scroll_region(&s, 2, 3));
+        assert_eq!(state.scroll_region(&s), 0..5);
+
+        assert!(state.set_scroll_region(&s, 1, 99));
+        assert_eq!(state.scroll_region(&s), 1..5);
+    }
+
+    #[test]
+    fn line_feed_scrolls_only_the_region() {
+        let mut s = surface_with_rows(&["AAA", "BBB", "CCC", "DDD"]);
+        let mut state = TerminalState::default();
+        state.set_scroll_region(&s, 1, 3);
+
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(2),
+        });
+        state.line_feed(&mut s);
+
+        assert_eq!(rows(&s), ["AAA", "CCC", "   ", "DDD"]);
+        assert_eq!(s.cursor_position(), (0, 2));
+    }
+
+    #[test]
+    fn reverse_index_scrolls_down_at_top_margin() {
+        let mut s = surface_with_rows(&["AAA", "BBB", "CCC", "DDD"]);
+        let mut state = TerminalState::default();
+        state.set_scroll_region(&s, 1, 3);
+
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(1),
+        });
+        state.reverse_index(&mut s);
+
+        assert_eq!(rows(&s), ["AAA", "   ", "BBB", "DDD"]);
+        assert_eq!(s.cursor_position(), (0, 1));
+    }
+
+    #[test]
+    fn line_feed_below_region_does_not_scroll() {
+        let mut s = surface_with_rows(&["AAA", "BBB", "CCC", "DDD"]);
+        let mut state = TerminalState::default();
+        state.set_scroll_region(&s, 0, 2);
+
+        s.add_change(Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(3),
+        });
+        state.line_feed(&mut s);
+
+        assert_eq!(rows(&s), ["AAA", "BBB", "CCC", "DDD"]);
+        assert_eq!(s.cursor_position(), (0, 3));
+    }
+
+    #[test]
+    fn alternate_screen_is_separate_from_primary() {
+        let mut s = surface_with_rows(&["AAA", "BBB"]);
+        let mut state = TerminalState::default();
+
+        state.enter_alternate_screen(&mut s, true);
+        assert_eq!(state.active_buffer, ScreenBuffer::Alternate);
+        assert_eq!(rows(&s), ["   ", "   "]);
+        s.add_change("X");
+
+        state.leave_alternate_screen(&mut s, false, true);
+        assert_eq!(state.active_buffer, ScreenBuffer::Primary);
+        assert_eq!(rows(&s), ["AAA", "BBB"]);
+        assert_eq!(s.cursor_position(), (2, 1));
+    }
+
+    #[test]
+    fn take_screen_returns_requested_buffer() {
+        let mut s = surface_with_rows(&["AAA"]);
+        let mut state = TerminalState::default();
+
+        state.enter_alternate_screen(&mut s, true);
+        s.add_change("\rX");
+        state.leave_alternate_screen(&mut s, true, true);
+
+        let alternate = state.take_screen(s.clone(), ScreenBuffer::Alternate);
+        assert_eq!(rows(&alternate)[0], "X  ");
+
+        let primary = state.take_screen(s, ScreenBuffer::Primary);
+        assert_eq!(rows(&primary)[0], "AAA");
+    }
+
+    #[test]
+    fn take_primary_while_alternate_is_active() {
+        let mut s = surface_with_rows(&["AAA"]);
+        let mut state = TerminalState::default();
+
+        state.enter_alternate_screen(&mut s, false);
+        s.add_change("X");
+
+        let primary = state.take_screen(s, ScreenBuffer::Primary);
+        assert_eq!(rows(&primary)[0], "AAA");
+    }
+}
@@ -0,0 +1,128 @@
+use crate::constants::TAB_WIDTH;
+
+/// Columns the cursor stops at on horizontal tab, set every `width` columns
+/// until the program changes them.
+#[derive(Debug)]
+pub struct TabStops {
+    stops: Vec<bool>,
+    /// Columns between the default stops
+    width: usize,
+    /// Whether every stop was cleared, so that columns past the known ones get none
+    cleared: bool,
+}
+
+impl Default for TabStops {
+    fn default() -> Self {
+        Self::new(TAB_WIDTH)
+    }
+}
+
+impl TabStops {
+    /// Tab stops every `width` columns.
+    pub fn new(width: usize) -> Self {
+        Self {
+            stops: Vec::new(),
+            width: width.max(1),
+            cleared: false,
+        }
+    }
+
+    /// Sets a tab stop at `col` (HTS).
+    pub fn set(&mut self, col: usize) {
+        self.grow(col + 1);
+        self.stops[col] = true;
+    }
+
+    /// Clears the tab stop at `col` (TBC 0).
+    pub fn clear(&mut self, col: usize) {
+        self.grow(col + 1);
+        self.stops[col] = false;
+    }
+
+    /// Clears every tab stop (TBC 3).
+    pub fn clear_all(&mut self) {
+        self.stops.fill(false);
+        self.cleared = true;
+    }
+
+    /// Restores the default stops every `width` columns.
+    pub fn reset(&mut self) {
+        self.stops.clear();
+        self.cleared = false;
+    }
+
+    /// Column of the next tab stop after `col`, or the last column when there is none.
+    pub fn next(&mut self, col: usize, width: usize) -> usize {
+        self.grow(width);
+        let last = width.saturating_sub(1);
+        (col + 1..last).find(|&x| self.stops[x]).unwrap_or(last)
+    }
+
+    /// Column of the previous tab stop before `col`, or the first column when there is none.
+    pub fn previous(&mut self, col: usize) -> usize {
+        self.grow(col);
+        (1..col).rev().find(|&x| self.stops[x]).unwrap_or(0)
+    }
+
+    fn grow(&mut self, len: usize) {
+        let start = self.stops.len();
+        if len > start {
+            let (width, cleared) = (self.width, self.cleared);
+            self.stops
+                .extend((start..len).map(|x| !cleared && x % width == 0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_stops() {
+        let mut stops = TabStops::default();
+        assert_eq!(stops.next(0, 20), 8);
+        assert_eq!(stops.next(8, 20), 16);
+        assert_eq!(stops.next(16, 20), 19);
+        assert_eq!(stops.next(19, 20), 19);
+    }
+
+    #[test]
+    fn test_set_stop() {
+        let mut stops = TabStops::default();
+        stops.set(3);
+        assert_eq!(stops.next(0, 20), 3);
+        assert_eq!(stops.next(3, 20), 8);
+    }
+
+    #[test]
+    fn test_custom_width() {
+        let mut stops = TabStops::new(4);
+        assert_eq!(stops.next(0, 20), 4);
+        assert_eq!(stops.next(5, 20), 8);
+        assert_eq!(stops.previous(8), 4);
+    }
+
+    #[test]
+    fn test_previous_stop() {
+        let mut stops = TabStops::default();
+        assert_eq!(stops.previous(10), 8);
+        assert_eq!(stops.previous(8), 0);
+        assert_eq!(stops.previous(0), 0);
+    }
+
+    #[test]
+    fn test_clear_stops() {
+        let mut stops = TabStops::default();
+        stops.clear(8);
+        assert_eq!(stops.next(0, 20), 16);
+
+        stops.clear_all();
+        stops.set(5);
+        assert_eq!(stops.next(0, 40), 5);
+        assert_eq!(stops.next(5, 40), 39);
+
+        stops.reset();
+        assert_eq!(stops.next(0, 40), 8);
+    }
+}
@@ -0,0 +1,63 @@
+use termwiz::escape::osc::Progress;
+
+/// Progress of a task, as last reported by the command with OSC 9;4
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TaskProgress {
+    /// No progress reported, or the report was cleared
+    #[default]
+    None,
+    /// Running, with a completion percentage
+    Normal(u8),
+    /// Failed, at a completion percentage
+    Error(u8),
+    /// Running, with no known completion
+    Indeterminate,
+    /// Paused, at a completion percentage
+    Paused(u8),
+}
+
+impl TaskProgress {
+    /// Progress after `report`. A pause keeps the percentage reached so far.
+    pub fn update(self, report: &Progress) -> Self {
+        match report {
+            Progress::None => Self::None,
+            Progress::SetPercentage(percent) => Self::Normal((*percent).min(100)),
+            Progress::SetError(percent) => Self::Error((*percent).min(100)),
+            Progress::SetIndeterminate => Self::Indeterminate,
+            Progress::Paused => Self::Paused(self.percent().unwrap_or(0)),
+        }
+    }
+
+    /// Completion percentage, when known.
+    pub fn percent(self) -> Option<u8> {
+        match self {
+            Self::Normal(percent) | Self::Error(percent) | Self::Paused(percent) => Some(percent),
+            Self::None | Self::Indeterminate => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let progress = TaskProgress::default().update(&Progress::SetPercentage(40));
+        assert_eq!(progress, TaskProgress::Normal(40));
+        assert_eq!(progress.update(&Progress::Paused), TaskProgress::Paused(40));
+        assert_eq!(
+            progress.update(&Progress::SetError(150)),
+            TaskProgress::Error(100)
+        );
+        assert_eq!(
+            progress.update(&Progress::SetIndeterminate),
+            TaskProgress::Indeterminate
+        );
+        assert_eq!(progress.update(&Progress::None), TaskProgress::None);
+        assert_eq!(
+            TaskProgress::Indeterminate.update(&Progress::Paused),
+            TaskProgress::Paused(0)
+        );
+    }
+}
@@ -1,9 +1,39 @@
-pub const TAB_WIDTH: usize = 8;
+use std::ops::Range;
 
-pub fn tabulate(pos: usize, n: usize) -> usize {
-    pos + (TAB_WIDTH * n - pos % TAB_WIDTH)
+use termwiz::{cell::Cell, surface::Surface};
+
+/// Overwrites the cells of `row` in the `cols` range with `cell`, clamped to the line width.
+pub fn fill_cells(surface: &mut Surface, row: usize, cols: Range<usize>, cell: &Cell) {
+    if let Some(line) = surface.screen_cells().into_iter().nth(row) {
+        let end = cols.end.min(line.len());
+        let start = cols.start.min(end);
+        line[start..end].fill(cell.clone());
+    }
+}
+
+/// Shifts the cells of `row` from `col` right by `count`, dropping the cells pushed
+/// past the right margin and filling the gap with `cell`.
+pub fn insert_cells(surface: &mut Surface, row: usize, col: usize, count: usize, cell: &Cell) {
+    if let Some(line) = surface.screen_cells().into_iter().nth(row)
+        && col < line.len()
+    {
+        let tail = &mut line[col..];
+        let count = count.min(tail.len());
+        tail.rotate_right(count);
+        tail[..count].fill(cell.clone());
+    }
 }
 
-pub fn tabulate_back(pos: usize, n: usize) -> usize {
-    pos.saturating_sub(pos % TAB_WIDTH + TAB_WIDTH * (n - 1))
+/// Shifts the cells of `row` after `col` left by `count`, filling the vacated cells at
+/// the right margin with `cell`.
+pub fn delete_cells(surface: &mut Surface, row: usize, col: usize, count: usize, cell: &Cell) {
+    if let Some(line) = surface.screen_cells().into_iter().nth(row)
+        && col < line.len()
+    {
+        let tail = &mut line[col..];
+        let count = count.min(tail.len());
+        tail.rotate_left(count);
+        let len = tail.len();
+        tail[len - count..].fill(cell.clone());
+    }
 }
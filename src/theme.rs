@@ -34,6 +34,8 @@ pub struct Theme {
     pub foreground_color: Rgba<u8>,
     /// Default background color
     pub background_color: Rgba<u8>,
+    /// Color of the cursor
+    pub cursor_color: Rgba<u8>,
 }
 
 impl Default for Theme {
@@ -69,6 +71,7 @@ impl Default for Theme {
             palette,
             foreground_color: palette[7], // light foreground
             background_color: palette[0], // dark background
+            cursor_color: palette[7],
         }
     }
 }
@@ -70,6 +70,7 @@ impl Base16 {
             palette: build_256_palette(ansi),
             foreground_color: hex_to_rgba(&theme.base05)?,
             background_color: hex_to_rgba(&theme.base00)?,
+            cursor_color: hex_to_rgba(&theme.base05)?,
         })
     }
 }
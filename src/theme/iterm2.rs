@@ -113,6 +113,7 @@ impl ITerm2 {
             palette: build_256_palette(ansi),
             foreground_color: theme.foreground.into(),
             background_color: theme.background.into(),
+            cursor_color: theme.cursor.into(),
         })
     }
 }
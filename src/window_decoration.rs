@@ -1,5 +1,6 @@
 use crate::{
     image_renderer::{ImageRendererError, canvas::Canvas, render_size::Size},
+    terminal_builder::TaskProgress,
     theme::Theme,
     window_decoration::{no_decoration::NoDecoration, windows::Windows},
 };
@@ -51,6 +52,29 @@ pub trait WindowDecoration: std::fmt::Debug {
         metrics: &WindowMetrics,
         theme: &Theme,
     ) -> Result<(), ImageRendererError>;
+
+    /// Draws `title` in the title bar; decorations without one draw nothing.
+    fn draw_title(
+        &self,
+        _canvas: &mut Canvas,
+        _metrics: &WindowMetrics,
+        _theme: &Theme,
+        _title: &str,
+    ) -> Result<(), ImageRendererError> {
+        Ok(())
+    }
+
+    /// Draws the progress reported by the command along the title bar;
+    /// decorations without one draw nothing.
+    fn draw_progress(
+        &self,
+        _canvas: &mut Canvas,
+        _metrics: &WindowMetrics,
+        _theme: &Theme,
+        _progress: TaskProgress,
+    ) -> Result<(), ImageRendererError> {
+        Ok(())
+    }
 }
 
 pub fn create_window_decoration(
@@ -117,6 +141,60 @@ mod tests {
                 result.is_ok(),
                 "draw_window failed for {decoration_type:?}: {result:?}",
             );
+
+            let result =
+                window_decoration.draw_title(&mut canvas, &metrics, &theme, "a very long title");
+            assert!(
+                result.is_ok(),
+                "draw_title failed for {decoration_type:?}: {result:?}",
+            );
+
+            let result = window_decoration.draw_progress(
+                &mut canvas,
+                &metrics,
+                &theme,
+                TaskProgress::Normal(50),
+            );
+            assert!(
+                result.is_ok(),
+                "draw_progress failed for {decoration_type:?}: {result:?}",
+            );
         }
     }
+
+    #[test]
+    fn test_progress_strip_length() {
+        let window_decoration = create_window_decoration(Some(&WindowDecorationType::Windows));
+        let font = window_decoration.font().expect("Font should be available");
+        let scale = PxScale::from(20.0);
+        let metrics = window_decoration.compute_metrics(calculate_char_size(&font.regular, scale));
+        let theme = Theme::default();
+
+        let strip_length = |progress| {
+            let mut canvas =
+                Canvas::new(200, 100, font.clone(), scale).expect("Failed to create Canvas");
+            canvas.fill(theme.background_color);
+            window_decoration
+                .draw_progress(&mut canvas, &metrics, &theme, progress)
+                .expect("draw_progress should succeed");
+            let image = canvas.to_final_image().expect("Failed to render canvas");
+            let y = metrics.title_bar_height - 1;
+            (0..image.width())
+                .filter(|&x| *image.get_pixel(x, y) != theme.background_color)
+                .count()
+        };
+
+        assert_eq!(strip_length(TaskProgress::None), 0);
+        assert_eq!(strip_length(TaskProgress::Normal(50)), 100);
+        assert_eq!(strip_length(TaskProgress::Error(0)), 200);
+        assert_eq!(strip_length(TaskProgress::Paused(25)), 50);
+    }
+
+    #[test]
+    fn test_truncate_title() {
+        assert_eq!(common::truncate_title("vim main.rs", 20), "vim main.rs");
+        assert_eq!(common::truncate_title("vim main.rs", 6), "vim m…");
+        assert_eq!(common::truncate_title("first\nsecond", 20), "first");
+        assert_eq!(common::truncate_title("title", 0), "");
+    }
 }
@@ -8,10 +8,11 @@ use crate::{
         render_size::Size,
         utils::{darken_color, lighten_color},
     },
+    terminal_builder::TaskProgress,
     theme::Theme,
     window_decoration::{
         Fonts, WindowMetrics,
-        common::{default_build_command_line, default_font},
+        common::{default_build_command_line, default_font, draw_progress_strip, draw_title_text},
     },
 };
 
@@ -55,6 +56,32 @@ impl WindowDecoration for Classic {
     ) -> Result<(), ImageRendererError> {
         draw_window_decorations(canvas, metrics, theme)
     }
+
+    fn draw_title(
+        &self,
+        canvas: &mut Canvas,
+        metrics: &WindowMetrics,
+        theme: &Theme,
+        title: &str,
+    ) -> Result<(), ImageRendererError> {
+        // Centered, keeping clear of the buttons on both sides
+        let buttons_width = metrics.title_bar_height * 5 / 2;
+        let left = metrics.border_width + buttons_width;
+        let right = canvas
+            .width()
+            .saturating_sub(metrics.border_width + buttons_width);
+        draw_title_text(canvas, metrics, theme, title, left..right, true)
+    }
+
+    fn draw_progress(
+        &self,
+        canvas: &mut Canvas,
+        metrics: &WindowMetrics,
+        theme: &Theme,
+        progress: TaskProgress,
+    ) -> Result<(), ImageRendererError> {
+        draw_progress_strip(canvas, metrics, theme, progress)
+    }
 }
 
 fn draw_window_decorations(
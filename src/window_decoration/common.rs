@@ -1,10 +1,18 @@
+use std::ops::Range;
+
 use ab_glyph::FontArc;
 use termwiz::{
     cell::{Cell, CellAttributes},
     color::ColorAttribute,
 };
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::{image_renderer::ImageRendererError, window_decoration::Fonts};
+use crate::{
+    image_renderer::{ImageRendererError, canvas::Canvas},
+    terminal_builder::TaskProgress,
+    theme::Theme,
+    window_decoration::{Fonts, WindowMetrics},
+};
 
 pub static DEJA_VU_FONT_DATA: &[u8] = include_bytes!("../../assets/DejaVuSansMono.ttf");
 pub static DEJA_VU_CODE_BOLD_FONT_DATA: &[u8] =
@@ -47,3 +55,112 @@ pub fn default_font() -> Result<Fonts, ImageRendererError> {
             .map_err(|_| ImageRendererError::FontLoadError)?,
     })
 }
+
+/// Draws `title` on a single line of the title bar, inside the horizontal `area`
+/// in pixels, shortening it with an ellipsis when it does not fit.
+pub fn draw_title_text(
+    canvas: &mut Canvas,
+    metrics: &WindowMetrics,
+    theme: &Theme,
+    title: &str,
+    area: Range<u32>,
+    centered: bool,
+) -> Result<(), ImageRendererError> {
+    let char_width = canvas.char_width().max(1);
+    let max_cols = (area.len() as u32 / char_width) as usize;
+    let title = truncate_title(title, max_cols);
+    if title.is_empty() {
+        return Ok(());
+    }
+
+    let text_width = u32::try_from(title.width())? * char_width;
+    let x = if centered {
+        area.start + (area.len() as u32).saturating_sub(text_width) / 2
+    } else {
+        area.start
+    };
+    let y = metrics.border_width
+        + metrics
+            .title_bar_height
+            .saturating_sub(canvas.char_height())
+            / 2;
+
+    canvas.draw_text(
+        &title,
+        i32::try_from(x)?,
+        i32::try_from(y)?,
+        theme,
+        &CellAttributes::blank(),
+    );
+
+    Ok(())
+}
+
+/// Draws `progress` as a strip along the bottom edge of the title bar, green
+/// while running, red on error and yellow when paused. An indeterminate
+/// progress fills the middle third, and a paused or failed one without a
+/// percentage fills the whole strip.
+pub fn draw_progress_strip(
+    canvas: &mut Canvas,
+    metrics: &WindowMetrics,
+    theme: &Theme,
+    progress: TaskProgress,
+) -> Result<(), ImageRendererError> {
+    let (color, fill) = match progress {
+        TaskProgress::None => return Ok(()),
+        TaskProgress::Normal(percent) => (theme.palette[2], (0, u32::from(percent))),
+        TaskProgress::Error(percent) => (theme.palette[1], (0, full_when_zero(percent))),
+        TaskProgress::Paused(percent) => (theme.palette[3], (0, full_when_zero(percent))),
+        TaskProgress::Indeterminate => (theme.palette[2], (100 / 3, 200 / 3)),
+    };
+
+    let width = canvas.width().saturating_sub(2 * metrics.border_width);
+    let height = (metrics.title_bar_height / 8).max(2);
+    let start = width * fill.0 / 100;
+    let end = width * fill.1 / 100;
+    if end <= start {
+        return Ok(());
+    }
+
+    let y = (metrics.border_width + metrics.title_bar_height).saturating_sub(height);
+    canvas.fill_rect(
+        i32::try_from(metrics.border_width + start)?,
+        i32::try_from(y)?,
+        end - start,
+        height,
+        color,
+    );
+
+    Ok(())
+}
+
+fn full_when_zero(percent: u8) -> u32 {
+    if percent == 0 {
+        100
+    } else {
+        u32::from(percent)
+    }
+}
+
+/// Keeps the first line of `title`, shortened to `max_cols` columns with an ellipsis.
+pub fn truncate_title(title: &str, max_cols: usize) -> String {
+    let title = title.lines().next().unwrap_or_default().trim();
+    if title.width() <= max_cols {
+        return title.to_string();
+    }
+
+    let mut truncated = String::new();
+    let mut used = 0;
+    for ch in title.chars() {
+        let width = ch.width().unwrap_or(0);
+        if used + width + 1 > max_cols {
+            break;
+        }
+        used += width;
+        truncated.push(ch);
+    }
+    if max_cols > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
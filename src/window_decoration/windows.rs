@@ -7,10 +7,11 @@ use crate::{
         render_size::Size,
         utils::darken_color,
     },
+    terminal_builder::TaskProgress,
     theme::Theme,
     window_decoration::{
         Fonts, WindowMetrics,
-        common::{default_build_command_line, default_font},
+        common::{default_build_command_line, default_font, draw_progress_strip, draw_title_text},
     },
 };
 
@@ -50,6 +51,33 @@ impl WindowDecoration for Windows {
     ) -> Result<(), ImageRendererError> {
         draw_window_decorations(canvas, metrics, theme)
     }
+
+    fn draw_title(
+        &self,
+        canvas: &mut Canvas,
+        metrics: &WindowMetrics,
+        theme: &Theme,
+        title: &str,
+    ) -> Result<(), ImageRendererError> {
+        // Left-aligned, up to the three caption buttons
+        let btn_size = metrics.title_bar_height;
+        let buttons_width = 3 * (btn_size + btn_size / 5);
+        let left = metrics.border_width + metrics.padding;
+        let right = canvas
+            .width()
+            .saturating_sub(metrics.border_width + buttons_width);
+        draw_title_text(canvas, metrics, theme, title, left..right, false)
+    }
+
+    fn draw_progress(
+        &self,
+        canvas: &mut Canvas,
+        metrics: &WindowMetrics,
+        theme: &Theme,
+        progress: TaskProgress,
+    ) -> Result<(), ImageRendererError> {
+        draw_progress_strip(canvas, metrics, theme, progress)
+    }
 }
 
 fn draw_window_decorations(